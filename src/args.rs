@@ -1,24 +1,145 @@
 use std::env;
 
+use crate::{color::ColorPolicy, import::Imports};
+
 /// A custom iterator to parse the arguments.
+/// - Leading arguments starting with '-' are parsed as options.
 /// - IntoIterator is implemented as the Iterator of the positional arguments.
 pub struct Args {
     iter: PositionalArgsIter,
+    /// Options given before the positional arguments
+    pub opts: ArgsOpts,
+}
+
+/// Options given before the positional arguments
+#[derive(Default)]
+pub struct ArgsOpts {
+    /// Show hidden tasks in the list
+    pub all: bool,
+    /// Answer yes to all confirmations
+    pub yes: bool,
+    /// Remove the outputs instead of executing
+    pub clean: bool,
+    /// Only show what would be done
+    pub dry_run: bool,
+    /// Show the resolved configuration instead of executing
+    pub show: bool,
+    /// Maximum number of scripts running at once
+    pub jobs: Option<usize>,
+    /// Record the executions to the history
+    pub record: bool,
+    /// Show the execution statistics instead of executing
+    pub history: bool,
+    /// Write the lockfile of the ruskfiles
+    pub lock: bool,
+    /// Fail if the ruskfiles differ from the lockfile
+    pub locked: bool,
+    /// Trust the ruskfiles outside the current repository
+    pub trust: bool,
+    /// List the tasks with their details for editors
+    pub details: bool,
+    /// Report the problems in the ruskfiles instead of executing
+    pub check: bool,
+    /// Show the definitions of the dependency at the location
+    pub definition: bool,
+    /// List the task names to complete in the ruskfile
+    pub complete: bool,
+    /// When to color the output
+    pub color: ColorPolicy,
+    /// Show the progress of the execution
+    pub progress: bool,
+    /// Variables overriding the `[vars]` of the ruskfiles
+    pub vars: Vec<(String, String)>,
+    /// Task runners to import the tasks from
+    pub imports: Imports,
+    /// Number of times to execute the tasks to measure their durations
+    pub bench: Option<usize>,
+}
+
+/// Error when parsing the arguments.
+#[derive(Debug, thiserror::Error)]
+pub enum ArgsError {
+    #[error("Unknown option: {0}")]
+    UnknownOption(String),
+    #[error("Invalid value for {option}: {value:?}")]
+    InvalidValue { option: String, value: String },
 }
 
 impl Args {
     /// Creates a new Args iterator.
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, ArgsError> {
         let mut inner = env::args();
         inner.next(); // Skip the first argument
-        let first = inner.next();
-        Self {
+        let mut opts = ArgsOpts::default();
+        let first = loop {
+            let Some(arg) = inner.next() else {
+                break None;
+            };
+            match arg.as_str() {
+                "--" => break inner.next(),
+                "-a" | "--all" => opts.all = true,
+                "-y" | "--yes" => opts.yes = true,
+                "--clean" => opts.clean = true,
+                "-n" | "--dry-run" => opts.dry_run = true,
+                "--show" => opts.show = true,
+                "--record" => opts.record = true,
+                "--history" => opts.history = true,
+                "--lock" => opts.lock = true,
+                "--locked" => opts.locked = true,
+                "--trust" => opts.trust = true,
+                "--details" => opts.details = true,
+                "--check" => opts.check = true,
+                "--definition" => opts.definition = true,
+                "--complete" => opts.complete = true,
+                "--progress" => opts.progress = true,
+                "-j" | "--jobs" => {
+                    let value = inner.next().unwrap_or_default();
+                    match value.parse() {
+                        Ok(jobs) if jobs > 0 => opts.jobs = Some(jobs),
+                        _ => return Err(ArgsError::InvalidValue { option: arg, value }),
+                    }
+                }
+                "--bench" => {
+                    let value = inner.next().unwrap_or_default();
+                    match value.parse() {
+                        Ok(iterations) if iterations > 0 => opts.bench = Some(iterations),
+                        _ => return Err(ArgsError::InvalidValue { option: arg, value }),
+                    }
+                }
+                "--var" => {
+                    let value = inner.next().unwrap_or_default();
+                    match value.split_once('=') {
+                        Some((name, var)) if !name.is_empty() => {
+                            opts.vars.push((name.to_owned(), var.to_owned()))
+                        }
+                        _ => return Err(ArgsError::InvalidValue { option: arg, value }),
+                    }
+                }
+                "--import" => {
+                    let value = inner.next().unwrap_or_default();
+                    if !opts.imports.enable(&value) {
+                        return Err(ArgsError::InvalidValue { option: arg, value });
+                    }
+                }
+                "--color" => {
+                    let value = inner.next().unwrap_or_default();
+                    match value.parse() {
+                        Ok(color) => opts.color = color,
+                        _ => return Err(ArgsError::InvalidValue { option: arg, value }),
+                    }
+                }
+                _ if arg.starts_with('-') => return Err(ArgsError::UnknownOption(arg)),
+                _ => break Some(arg),
+            }
+        };
+        Ok(Self {
             iter: PositionalArgsIter {
                 inner,
                 first,
                 first_read: false,
             },
-        }
+            opts,
+        })
     }
     /// Whether or not there are no positional arguments.
     pub fn no_pargs(&self) -> bool {
@@ -0,0 +1,93 @@
+//! Statistics of the repeated executions of a task.
+
+use std::{fmt::Display, time::Duration};
+
+use colored::Colorize;
+
+/// Durations of the script measured by [crate::rusk::Rusk::bench]
+#[derive(Debug, Clone)]
+pub struct BenchStats {
+    /// Durations of the executions, sorted in ascending order
+    samples: Vec<Duration>,
+}
+
+impl BenchStats {
+    /// Create from the durations of the executions
+    pub fn new(mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        Self { samples }
+    }
+
+    /// Shortest duration
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.first().copied()
+    }
+
+    /// Average duration
+    pub fn mean(&self) -> Option<Duration> {
+        let runs = self.samples.len();
+        (runs > 0).then(|| self.samples.iter().sum::<Duration>() / runs as u32)
+    }
+
+    /// 95th percentile duration by the nearest-rank method
+    pub fn p95(&self) -> Option<Duration> {
+        let rank = (self.samples.len() * 95).div_ceil(100);
+        self.samples.get(rank.checked_sub(1)?).copied()
+    }
+}
+
+impl Display for BenchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (Some(min), Some(mean), Some(p95)) = (self.min(), self.mean(), self.p95()) else {
+            return "no runs measured".dimmed().fmt(f);
+        };
+        writeln!(f, "{} {}", "runs".dimmed(), self.samples.len())?;
+        writeln!(f, "{} {:.2?}", "min".dimmed(), min)?;
+        writeln!(f, "{} {:.2?}", "mean".dimmed(), mean)?;
+        write!(f, "{} {:.2?}", "p95".dimmed(), p95)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(millis: &[u64]) -> BenchStats {
+        BenchStats::new(millis.iter().map(|&ms| Duration::from_millis(ms)).collect())
+    }
+
+    #[test]
+    fn no_samples() {
+        let stats = stats(&[]);
+        assert_eq!((stats.min(), stats.mean(), stats.p95()), (None, None, None));
+    }
+
+    #[test]
+    fn single_sample() {
+        let stats = stats(&[7]);
+        let expected = Some(Duration::from_millis(7));
+        assert_eq!(
+            (stats.min(), stats.mean(), stats.p95()),
+            (expected, expected, expected)
+        );
+    }
+
+    #[test]
+    fn unsorted_samples() {
+        let stats = stats(&[30, 10, 20]);
+        assert_eq!(stats.min(), Some(Duration::from_millis(10)));
+        assert_eq!(stats.mean(), Some(Duration::from_millis(20)));
+        assert_eq!(stats.p95(), Some(Duration::from_millis(30)));
+    }
+
+    #[test]
+    fn p95_nearest_rank() {
+        // Rank of 20 samples is 19, so the slowest one is excluded
+        let samples: Vec<u64> = (1..=20).collect();
+        assert_eq!(stats(&samples).p95(), Some(Duration::from_millis(19)));
+        // Rank of 19 samples is ceil(18.05) = 19
+        assert_eq!(stats(&samples[..19]).p95(), Some(Duration::from_millis(19)));
+        let samples: Vec<u64> = (1..=100).collect();
+        assert_eq!(stats(&samples).p95(), Some(Duration::from_millis(95)));
+    }
+}
@@ -0,0 +1,215 @@
+//! Policy whether to color the output.
+
+use std::{
+    fmt::{Display, Write},
+    io::IsTerminal,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Environment variable to disable coloring. See <https://no-color.org>.
+const NO_COLOR_ENV: &str = "NO_COLOR";
+/// Environment variable to color even if the stream is not a terminal
+const CLICOLOR_FORCE_ENV: &str = "CLICOLOR_FORCE";
+/// Environment variable to disable coloring when set to `0`
+const CLICOLOR_ENV: &str = "CLICOLOR";
+
+/// Whether stdout is colored, decided by [ColorPolicy::apply]
+static STDOUT_COLORED: AtomicBool = AtomicBool::new(true);
+/// Whether stderr is colored, decided by [ColorPolicy::apply]
+static STDERR_COLORED: AtomicBool = AtomicBool::new(true);
+
+/// When to color the output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorPolicy {
+    /// Only for the streams which are terminals, following `NO_COLOR`, `CLICOLOR_FORCE` and `CLICOLOR`
+    #[default]
+    Auto,
+    /// Always
+    Always,
+    /// Never
+    Never,
+}
+
+impl FromStr for ColorPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorPolicy::Auto),
+            "always" => Ok(ColorPolicy::Always),
+            "never" => Ok(ColorPolicy::Never),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Output stream of the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    /// Whether the stream is a terminal
+    fn is_terminal(self) -> bool {
+        match self {
+            Stream::Stdout => std::io::stdout().is_terminal(),
+            Stream::Stderr => std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// Flag holding whether the stream is colored
+    fn colored(self) -> &'static AtomicBool {
+        match self {
+            Stream::Stdout => &STDOUT_COLORED,
+            Stream::Stderr => &STDERR_COLORED,
+        }
+    }
+}
+
+impl ColorPolicy {
+    /// Whether to color the stream under the policy
+    pub fn enabled(self, stream: Stream) -> bool {
+        match self {
+            ColorPolicy::Always => true,
+            ColorPolicy::Never => false,
+            ColorPolicy::Auto => {
+                let env = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+                if env(NO_COLOR_ENV).is_some() {
+                    return false;
+                }
+                if env(CLICOLOR_FORCE_ENV).is_some_and(|value| value != "0") {
+                    return true;
+                }
+                env(CLICOLOR_ENV).is_none_or(|value| value != "0") && stream.is_terminal()
+            }
+        }
+    }
+
+    /// Apply the policy to each stream
+    pub fn apply(self) {
+        let stdout = self.enabled(Stream::Stdout);
+        let stderr = self.enabled(Stream::Stderr);
+        STDOUT_COLORED.store(stdout, Ordering::Relaxed);
+        STDERR_COLORED.store(stderr, Ordering::Relaxed);
+        // NOTE: `colored` has a single switch, so the stream without color strips them in [Painted]
+        colored::control::set_override(stdout || stderr);
+    }
+}
+
+/// Display the value for stdout, without colors unless stdout is colored
+pub fn stdout<T: Display>(inner: T) -> Painted<T> {
+    Painted {
+        inner,
+        stream: Stream::Stdout,
+    }
+}
+
+/// Display the value for stderr, without colors unless stderr is colored
+pub fn stderr<T: Display>(inner: T) -> Painted<T> {
+    Painted {
+        inner,
+        stream: Stream::Stderr,
+    }
+}
+
+/// Value displayed with the colors of the stream it is written to
+pub struct Painted<T> {
+    inner: T,
+    stream: Stream,
+}
+
+impl<T: Display> Display for Painted<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.stream.colored().load(Ordering::Relaxed) {
+            self.inner.fmt(f)
+        } else {
+            let mut plain = StripEscapes {
+                inner: f,
+                state: EscapeState::Text,
+            };
+            write!(plain, "{}", self.inner)
+        }
+    }
+}
+
+/// Position in the ANSI escape sequences
+#[derive(Clone, Copy)]
+enum EscapeState {
+    /// Out of the sequences
+    Text,
+    /// Right after ESC
+    Escape,
+    /// In the parameters of CSI, until the final byte
+    Csi,
+}
+
+/// Writer dropping the ANSI escape sequences
+struct StripEscapes<W> {
+    inner: W,
+    state: EscapeState,
+}
+
+impl<W: Write> Write for StripEscapes<W> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        // NOTE: Sequences may be split across the writes, so the state is kept between them
+        let mut text_start = 0;
+        for (i, c) in s.char_indices() {
+            let state = match (self.state, c) {
+                (EscapeState::Text, '\x1b') => {
+                    self.inner.write_str(&s[text_start..i])?;
+                    EscapeState::Escape
+                }
+                (EscapeState::Text, _) => continue,
+                (EscapeState::Escape, '[') => EscapeState::Csi,
+                (EscapeState::Escape, _) | (EscapeState::Csi, '\x40'..='\x7e') => {
+                    text_start = i + c.len_utf8();
+                    EscapeState::Text
+                }
+                (EscapeState::Csi, _) => EscapeState::Csi,
+            };
+            self.state = state;
+        }
+        if let EscapeState::Text = self.state {
+            self.inner.write_str(&s[text_start..])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(chunks: &[&str]) -> String {
+        let mut plain = StripEscapes {
+            inner: String::new(),
+            state: EscapeState::Text,
+        };
+        for chunk in chunks {
+            plain.write_str(chunk).unwrap();
+        }
+        plain.inner
+    }
+
+    #[test]
+    fn strip_color_sequences() {
+        assert_eq!(strip(&["\x1b[1;31merror\x1b[0m: failed"]), "error: failed");
+        assert_eq!(strip(&["plain ✓"]), "plain ✓");
+    }
+
+    #[test]
+    fn strip_split_sequences() {
+        assert_eq!(strip(&["a\x1b", "[3", "2mb\x1b[", "0m", "c"]), "abc");
+    }
+
+    #[test]
+    fn explicit_policies() {
+        for stream in [Stream::Stdout, Stream::Stderr] {
+            assert!(ColorPolicy::Always.enabled(stream));
+            assert!(!ColorPolicy::Never.enabled(stream));
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Environment variables of tasks, including those computed at runtime.
+
+use std::{cell::RefCell, collections::BTreeMap, ffi::OsString, fmt::Display, rc::Rc};
+
+use futures::{
+    FutureExt,
+    future::{LocalBoxFuture, Shared},
+};
+use hashbrown::HashMap;
+
+use crate::{
+    path::NormarizedPath,
+    runner::{RunRequest, TaskRunner},
+    rusk::{IOSet, parse_script},
+};
+
+/// Value of an environment variable
+#[derive(Clone)]
+pub enum EnvValue {
+    /// Literal value
+    Static(OsString),
+    /// Value computed from the stdout of the command
+    Command(String),
+}
+
+impl Display for EnvValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvValue::Static(value) => value.to_string_lossy().fmt(f),
+            EnvValue::Command(cmd) => write!(f, "$({cmd})"),
+        }
+    }
+}
+
+/// Origin of an environment variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvSource {
+    /// Given from ExecuteOpts, usually the environment of the process
+    Global,
+    /// Defined at the top level of the ruskfile
+    File,
+    /// Defined in the task
+    Task,
+}
+
+impl Display for EnvSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvSource::Global => "global",
+            EnvSource::File => "file",
+            EnvSource::Task => "task",
+        }
+        .fmt(f)
+    }
+}
+
+/// Result of the env command evaluation
+type EnvCommandResult = Result<OsString, String>;
+
+/// Evaluation of the env command which can be awaited from multiple tasks
+type EnvCommandFuture = Shared<LocalBoxFuture<'static, EnvCommandResult>>;
+
+/// Command, working directory and environment variables of an env command
+type EnvCommandKey = (String, NormarizedPath, BTreeMap<OsString, OsString>);
+
+/// Cache of env commands evaluated in one run.
+/// - The same command in the same directory with the same environment is evaluated only once.
+#[derive(Clone)]
+pub struct EnvCommandCache {
+    /// Map of the commands to the evaluation
+    cache: Rc<RefCell<HashMap<EnvCommandKey, EnvCommandFuture>>>,
+    /// IO set. The stdout is replaced to capture the output.
+    io: IOSet,
+    /// Backend to run the commands
+    runner: Rc<dyn TaskRunner>,
+}
+
+impl EnvCommandCache {
+    /// Create a new EnvCommandCache
+    pub fn new(io: IOSet, runner: Rc<dyn TaskRunner>) -> Self {
+        Self {
+            cache: Default::default(),
+            io,
+            runner,
+        }
+    }
+
+    /// Evaluate the command in the directory with the environment variables, or get the cached result
+    pub async fn evaluate(
+        &self,
+        cmd: &str,
+        cwd: &NormarizedPath,
+        envs: &std::collections::HashMap<OsString, OsString>,
+    ) -> EnvCommandResult {
+        let envs: BTreeMap<_, _> = envs.clone().into_iter().collect();
+        let future = self
+            .cache
+            .borrow_mut()
+            .entry((cmd.to_owned(), cwd.clone(), envs.clone()))
+            .or_insert_with(|| {
+                run_command(
+                    cmd.to_owned(),
+                    cwd.clone(),
+                    envs,
+                    self.io.clone(),
+                    self.runner.clone(),
+                )
+                .boxed_local()
+                .shared()
+            })
+            .clone(); // Release the borrow before awaiting
+        future.await
+    }
+}
+
+/// Run the command and capture its stdout
+async fn run_command(
+    cmd: String,
+    cwd: NormarizedPath,
+    envs: BTreeMap<OsString, OsString>,
+    io: IOSet,
+    runner: Rc<dyn TaskRunner>,
+) -> EnvCommandResult {
+    let parsed = parse_script(Some(&cmd)).map_err(|err| err.to_string())?;
+
+    let (reader, writer) = deno_task_shell::pipe();
+    let stdout = tokio::task::spawn_blocking(move || {
+        let mut buf = Vec::new();
+        reader
+            .pipe_to(&mut buf)
+            .map(|_| buf)
+            .map_err(|err| err.to_string())
+    });
+    let exit_code = runner
+        .run(RunRequest {
+            script: cmd.clone(),
+            parsed,
+            envs: envs.into_iter().collect(),
+            cwd: cwd.to_path_buf(),
+            stdin: io.stdin,
+            stdout: writer,
+            stderr: io.stderr,
+            kill_signal: Default::default(),
+        })
+        .await;
+    let stdout = stdout.await.map_err(|err| err.to_string())??;
+    if exit_code != 0 {
+        return Err(format!("`{cmd}` failed with exit code {exit_code}"));
+    }
+
+    // Trailing newlines are removed like the command substitution of shells
+    let stdout = String::from_utf8(stdout).map_err(|err| err.to_string())?;
+    Ok(stdout.trim_end_matches(['\n', '\r']).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use crate::{path::get_current_dir, runner::MockRunner};
+
+    use super::*;
+
+    fn envs(value: &str) -> std::collections::HashMap<OsString, OsString> {
+        [("A".into(), value.into())].into_iter().collect()
+    }
+
+    #[tokio::test]
+    async fn commands_are_cached_by_envs() {
+        let runner = Rc::new(MockRunner::default());
+        let cache = EnvCommandCache::new(IOSet::default(), runner.clone());
+        let cwd = get_current_dir();
+        for value in ["x", "x", "y"] {
+            cache.evaluate("echo $A", cwd, &envs(value)).await.unwrap();
+        }
+        let values: Vec<_> = runner
+            .envs
+            .borrow()
+            .iter()
+            .map(|envs| envs[OsStr::new("A")].clone())
+            .collect();
+        assert_eq!(values, ["x", "y"]);
+    }
+
+    #[tokio::test]
+    async fn failing_command_is_error() {
+        let runner = Rc::new(MockRunner {
+            failing: vec!["false"],
+            ..Default::default()
+        });
+        let cache = EnvCommandCache::new(IOSet::default(), runner);
+        let res = cache.evaluate("false", get_current_dir(), &envs("x")).await;
+        assert!(res.is_err());
+    }
+}
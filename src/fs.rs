@@ -2,21 +2,28 @@ use std::{
     borrow::Cow,
     ffi::{OsStr, OsString},
     fmt::Display,
+    io,
+    ops::Range,
     path::Path,
+    time::SystemTime,
 };
 
-use anyhow::Error;
 use colored::Colorize;
 use futures::future::join_all;
-use hashbrown::{HashMap, hash_map::EntryRef};
+use hashbrown::{HashMap, HashSet, hash_map::EntryRef};
 use ignore::{WalkBuilder, WalkState};
 use itertools::Itertools;
-use toml::Table;
+use toml::{Spanned, Table};
 use unicode_width::UnicodeWidthStr;
 
 use crate::{
+    envs::EnvValue,
+    import::{Adapter, ImportedTask, Imports},
+    lock::{Lockfile, content_hash},
     path::NormarizedPath,
     rusk::Task,
+    sandbox::{SandboxConfig, WritePath},
+    service::{ReadyCheck, ReadyCondition, ServiceConfig},
     taskkey::{TaskKey, TaskKeyRef, TaskKeyRelative},
 };
 
@@ -24,11 +31,38 @@ use crate::{
 #[derive(Default)]
 pub struct RuskfileComposer {
     /// Map of rusk.toml files
-    map: HashMap<NormarizedPath, Result<RuskfileDeserializer, String>>,
+    map: HashMap<NormarizedPath, Result<RuskfileDeserializer, RuskfileLoadError>>,
+    /// Content hashes of the rusk.toml files
+    hashes: HashMap<NormarizedPath, String>,
+    /// Contents of the rusk.toml files
+    sources: HashMap<NormarizedPath, String>,
+    /// Task runners whose files are also loaded
+    imports: Imports,
+}
+
+/// Error when loading a rusk.toml file
+pub struct RuskfileLoadError {
+    /// Error message with the excerpt of the content
+    message: String,
+    /// Error message without the excerpt
+    reason: String,
+    /// Byte range of the content where the error occurred
+    span: Option<Range<usize>>,
+}
+
+impl RuskfileLoadError {
+    /// Error message without the excerpt
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+    /// Byte range of the content where the error occurred
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
 }
 
 /// Check if the filename is ruskfile
-fn is_ruskfile(name: &OsStr) -> bool {
+pub fn is_ruskfile(name: &OsStr) -> bool {
     let Some(name) = name.to_str() else {
         return false;
     };
@@ -62,7 +96,9 @@ impl Display for TasksListItemPretty<'_> {
 
         let width = self.task_word_width + 2;
         match content {
-            Ok(TaskListItemContent { key, description }) => {
+            Ok(TaskListItemContent {
+                key, description, ..
+            }) => {
                 // (task_name)
                 let task_key = key.as_task_key();
                 write!(f, "{}", task_key)?;
@@ -143,12 +179,82 @@ impl Ord for TasksListItem<'_> {
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd)]
-struct TaskListItemContent<'a> {
+impl<'a> TasksListItem<'a> {
+    /// Task content, or the error message if the rusk.toml failed to load
+    pub fn content(&self) -> Result<&TaskListItemContent<'a>, &'a str> {
+        self.content.as_ref().map_err(|err| *err)
+    }
+    /// Path to rusk.toml
+    pub fn path(&self) -> &'a NormarizedPath {
+        self.path
+    }
+}
+
+/// Task of the tasks_list
+pub struct TaskListItemContent<'a> {
     /// TaskKey
     key: TaskKeyRef<'a>,
     /// Task description
     description: Option<&'a str>,
+    /// Raw content of the task
+    task: &'a TaskDeserializer,
+    /// Directory of rusk.toml
+    dir: &'a Path,
+    /// Variables of rusk.toml
+    vars: &'a HashMap<String, String>,
+    /// Line number where the task is defined
+    line: usize,
+}
+
+impl<'a> TaskListItemContent<'a> {
+    /// TaskKey
+    pub fn key(&self) -> &TaskKey {
+        self.key.as_task_key()
+    }
+    /// Task description
+    pub fn description(&self) -> Option<&'a str> {
+        self.description
+    }
+    /// Line number where the task is defined
+    pub fn line(&self) -> usize {
+        self.line
+    }
+    /// Whether the task is file-backed
+    pub fn is_file(&self) -> bool {
+        matches!(self.key(), TaskKey::File(_))
+    }
+    /// Dependencies. Invalid ones are skipped; they are reported when executing.
+    pub fn depends(&self) -> Vec<TaskKey> {
+        let Some(depends) = self.task.inner.get("depends").and_then(|v| v.as_array()) else {
+            return Vec::new();
+        };
+        depends
+            .iter()
+            .filter_map(|key| TaskKeyRelative::try_from(key.as_str()?.to_owned()).ok())
+            .map(|key| key.into_task_key(self.dir))
+            .collect()
+    }
+    /// Working directory, with the variables of rusk.toml interpolated
+    pub fn cwd(&self) -> NormarizedPath {
+        let cwd = self.task.inner.get("cwd").and_then(|v| v.as_str());
+        self.dir
+            .join(interpolate(cwd.unwrap_or_default(), self.vars))
+            .into()
+    }
+}
+
+impl Eq for TaskListItemContent<'_> {}
+
+impl PartialEq for TaskListItemContent<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl PartialOrd for TaskListItemContent<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl Ord for TaskListItemContent<'_> {
@@ -157,6 +263,40 @@ impl Ord for TaskListItemContent<'_> {
     }
 }
 
+/// Display the task of TasksListItem with its details for editors
+pub struct TasksListItemDetailed<'a> {
+    /// Task content
+    content: TaskListItemContent<'a>,
+    /// Path to rusk.toml
+    path: &'a NormarizedPath,
+}
+
+impl Display for TasksListItemDetailed<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        ////////////////////////////////////////////////
+        //
+        // Format:
+        //     (task_name)\t(file|phony)\t(description)\t(depends)\t(cwd)\t(path):(line)
+        //
+        ////////////////////////////////////////////////
+
+        let Self { content, path } = self;
+        let kind = if content.is_file() { "file" } else { "phony" };
+        let depends = content.depends();
+        write!(
+            f,
+            "{:#}\t{}\t{}\t{}\t{}\t{}:{}",
+            content.key(),
+            kind,
+            content.description().unwrap_or_default(),
+            depends.iter().map(|key| format!("{key:#}")).join(","),
+            content.cwd().as_short_str(),
+            path.as_short_str(),
+            content.line(),
+        )
+    }
+}
+
 impl Display for TasksListItem<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         ////////////////////////////////////////////////
@@ -175,9 +315,11 @@ impl Display for TasksListItem<'_> {
         }
 
         match &self.content {
-            Ok(TaskListItemContent { key, description }) => {
+            Ok(TaskListItemContent {
+                key, description, ..
+            }) => {
                 // (task_name)
-                writet!(key);
+                writet!(format_args!("{:#}", key));
                 if let Some(description) = description {
                     // (description)
                     writet!(description);
@@ -202,27 +344,106 @@ impl RuskfileComposer {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            hashes: HashMap::new(),
+            sources: HashMap::new(),
+            imports: Imports::default(),
+        }
+    }
+    /// Also load the files of other task runners, exposing their tasks as namespaced phony tasks
+    pub fn with_imports(mut self, imports: Imports) -> Self {
+        self.imports = imports;
+        self
+    }
+    /// Imported tasks hidden by the task of the same name in rusk.toml or imported from a file nearer to the root.
+    /// - Imported tasks are phony, so e.g. `npm:test` of each package.json would collide otherwise.
+    fn shadowed(&self) -> HashSet<(&NormarizedPath, &TaskKeyRelative)> {
+        let is_imported =
+            |path: &NormarizedPath| !is_ruskfile(path.file_name().unwrap_or_default());
+        // NOTE: Sorted by the precedence: rusk.toml files first, then the files nearer to the root
+        let configs = self
+            .map
+            .iter()
+            .filter_map(|(path, res)| Some((path, res.as_ref().ok()?)))
+            .sorted_by(|(a, _), (b, _)| {
+                (is_imported(a), a.components().count(), a.as_abs_str()).cmp(&(
+                    is_imported(b),
+                    b.components().count(),
+                    b.as_abs_str(),
+                ))
+            });
+        let mut defined = HashSet::new();
+        let mut shadowed = HashSet::new();
+        for (path, config) in configs {
+            for key in config.tasks.keys() {
+                if !defined.insert(key) && is_imported(path) {
+                    shadowed.insert((path, key));
+                }
+            }
         }
+        shadowed
     }
-    /// List all tasks
-    pub fn tasks_list(&self) -> impl Iterator<Item = TasksListItem<'_>> {
+    /// List all tasks. Hidden tasks are included only if `include_hidden` is true.
+    pub fn tasks_list(&self, include_hidden: bool) -> impl Iterator<Item = TasksListItem<'_>> {
+        let shadowed = self.shadowed();
         self.map
             .iter()
-            .filter_map(|(path, res)| match res {
-                Ok(config) => Some(config.tasks.iter().map(move |(key, task)| TasksListItem {
+            .filter_map(|(path, res)| Some((path, res.as_ref().ok()?)))
+            .flat_map(|(path, config)| {
+                config
+                    .tasks
+                    .iter()
+                    .map(move |(key, task)| (path, config, key, task))
+            })
+            .filter(move |(path, _, key, task)| {
+                (include_hidden || !task.get_ref().hidden) && !shadowed.contains(&(*path, *key))
+            })
+            .map(|(path, config, key, task)| {
+                let dir = Path::parent(path).unwrap();
+                TasksListItem {
                     content: Ok(TaskListItemContent {
-                        key: key.as_task_key(Path::parent(path).unwrap()),
-                        description: task.description.as_deref(),
+                        key: key.as_task_key(dir),
+                        description: task.get_ref().description.as_deref(),
+                        task: task.get_ref(),
+                        dir,
+                        vars: &config.vars,
+                        line: line_of(&config.line_starts, task.span().start),
                     }),
                     path,
-                })),
-                _ => None,
+                }
             })
-            .flatten()
+    }
+    /// List the tasks defined in rusk.toml files below the directory, sorted.
+    /// Hidden tasks are included only if `include_hidden` is true.
+    pub fn tasks_list_for_dir<'a>(
+        &'a self,
+        dir: &'a Path,
+        include_hidden: bool,
+    ) -> impl Iterator<Item = TasksListItem<'a>> {
+        self.tasks_list(include_hidden)
+            .filter(move |item| item.path.starts_with(dir))
+            .sorted()
+    }
+    /// List the tasks below the directory with their details & sorted
+    pub fn tasks_list_detailed<'a>(
+        &'a self,
+        dir: &'a Path,
+        include_hidden: bool,
+    ) -> impl Iterator<Item = TasksListItemDetailed<'a>> {
+        self.tasks_list_for_dir(dir, include_hidden).filter_map(
+            |TasksListItem { content, path }| {
+                Some(TasksListItemDetailed {
+                    content: content.ok()?,
+                    path,
+                })
+            },
+        )
     }
     /// List all tasks with pretty format & sorted
-    pub fn tasks_list_pretty(&self) -> impl Iterator<Item = TasksListItemPretty<'_>> {
-        let tasks: Vec<_> = self.tasks_list().sorted().collect();
+    pub fn tasks_list_pretty(
+        &self,
+        include_hidden: bool,
+    ) -> impl Iterator<Item = TasksListItemPretty<'_>> {
+        let tasks: Vec<_> = self.tasks_list(include_hidden).sorted().collect();
         let task_word_width = tasks
             .iter()
             .map(|a| {
@@ -239,11 +460,29 @@ impl RuskfileComposer {
             task_word_width,
         })
     }
+    /// List all successfully loaded rusk.toml files
+    pub fn ruskfiles(&self) -> impl Iterator<Item = &NormarizedPath> {
+        self.map
+            .iter()
+            .filter_map(|(path, res)| res.is_ok().then_some(path))
+    }
+    /// Content of the rusk.toml file, if it was readable
+    pub fn source(&self, path: &NormarizedPath) -> Option<&str> {
+        self.sources.get(path).map(String::as_str)
+    }
+    /// List all rusk.toml files with their contents and load errors
+    pub fn sources(
+        &self,
+    ) -> impl Iterator<Item = (&NormarizedPath, Option<&str>, Option<&RuskfileLoadError>)> {
+        self.map
+            .iter()
+            .map(|(path, res)| (path, self.source(path), res.as_ref().err()))
+    }
     /// List all errors
     pub fn errors_list(&self) -> impl Iterator<Item = TasksListItem<'_>> {
         self.map.iter().filter_map(|(path, res)| match res {
             Err(err) => Some(TasksListItem {
-                content: Err(err),
+                content: Err(&err.message),
                 path,
             }),
             _ => None,
@@ -251,7 +490,9 @@ impl RuskfileComposer {
     }
 
     /// Walk through the directory and find all rusk.toml files
+    /// - The files of other task runners are also found if they are imported.
     pub async fn walkdir(&mut self, path: impl AsRef<Path>) {
+        let imports = self.imports;
         let threads = {
             let (tx, mut rx) = tokio::sync::mpsc::channel(0x1000);
             tokio::task::spawn_blocking({
@@ -266,19 +507,24 @@ impl RuskfileComposer {
                                 if let Ok(entry) = res
                                     && let Some(ft) = entry.file_type()
                                 {
-                                    if ft.is_file() && is_ruskfile(entry.file_name()) {
+                                    let name = entry.file_name();
+                                    let adapter = imports.adapter(name);
+                                    if ft.is_file() && (is_ruskfile(name) || adapter.is_some()) {
                                         let path = NormarizedPath::from(entry.path());
                                         tx.blocking_send(async move {
                                             // make Future of Config
-                                            let res = tokio::fs::read_to_string(&path)
-                                                .await
-                                                .map_err(Error::from)
-                                                .and_then(|content| {
-                                                    toml::from_str::<RuskfileDeserializer>(&content)
-                                                        .map_err(Error::from)
-                                                })
-                                                .map_err(|err| err.to_string());
-                                            (path, res)
+                                            let content = tokio::fs::read_to_string(&path).await;
+                                            let res = match &content {
+                                                Ok(content) => {
+                                                    RuskfileDeserializer::load(content, adapter)
+                                                }
+                                                Err(err) => Err(RuskfileLoadError {
+                                                    message: err.to_string(),
+                                                    reason: err.to_string(),
+                                                    span: None,
+                                                }),
+                                            };
+                                            (path, content.ok(), res)
                                         })
                                         .unwrap();
                                     }
@@ -296,14 +542,120 @@ impl RuskfileComposer {
             }
             threads
         };
-        self.map.extend(join_all(threads).await);
+        for (path, content, res) in join_all(threads).await {
+            if let Some(content) = content {
+                self.hashes
+                    .insert(path.clone(), content_hash(content.as_bytes()));
+                self.sources.insert(path.clone(), content);
+            }
+            self.map.insert(path, res);
+        }
+    }
+
+    /// Load the file from the content instead of the filesystem
+    #[cfg(test)]
+    pub fn insert_source(&mut self, path: &str, content: &str) {
+        let path = NormarizedPath::from(Path::new(path));
+        let adapter = self.imports.adapter(path.file_name().unwrap());
+        let res = RuskfileDeserializer::load(content, adapter);
+        self.sources.insert(path.clone(), content.to_owned());
+        self.map.insert(path, res);
+    }
+
+    /// Pin the content of the rusk.toml files, with the paths relative to `root`
+    pub fn lockfile(&self, root: &Path) -> Lockfile {
+        let mut lockfile = Lockfile::default();
+        for (path, hash) in &self.hashes {
+            lockfile.insert(root, path, hash.clone());
+        }
+        lockfile
+    }
+}
+
+/// Get the newest modified datetime among the entries under the directory.
+/// - Files excluded by ignore rules such as .gitignore are not considered.
+pub async fn newest_modified(dir: impl AsRef<Path>) -> io::Result<SystemTime> {
+    let dir = dir.as_ref().to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut newest = SystemTime::UNIX_EPOCH;
+        for entry in WalkBuilder::new(dir).follow_links(true).build() {
+            let modified = entry
+                .and_then(|entry| entry.metadata())
+                .map_err(io::Error::other)?
+                .modified()?;
+            newest = newest.max(modified);
+        }
+        Ok(newest)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// Location where a task is defined
+#[derive(Debug, Clone)]
+pub struct TaskLocation {
+    /// Path to the ruskfile
+    pub path: NormarizedPath,
+    /// Line number (1-origin)
+    pub line: usize,
+}
+
+impl Display for TaskLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.path, self.line)
     }
 }
 
+/// Byte offsets where each line starts
+pub fn line_starts(content: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+/// Replace `{{name}}` in the template with the value of the variable.
+/// - Names which are not defined are left as they are, so that other template syntaxes pass through.
+fn interpolate(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let inside = &rest[start + 2..];
+        let value = inside
+            .find("}}")
+            .and_then(|end| Some((vars.get(inside[..end].trim())?, end)));
+        match value {
+            Some((value, end)) => {
+                rendered.push_str(value);
+                rest = &inside[end + 2..];
+            }
+            None => {
+                rendered.push_str("{{");
+                rest = inside;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Line number (1-based) of the byte offset
+pub fn line_of(line_starts: &[usize], offset: usize) -> usize {
+    line_starts.partition_point(|&i| i <= offset)
+}
+
+/// Display duplicated tasks with their locations
+fn fmt_duplicates(duplicates: &[(TaskKey, Vec<TaskLocation>)]) -> String {
+    duplicates
+        .iter()
+        .map(|(key, locations)| format!("\n  {key} in {}", locations.iter().join(", ")))
+        .collect()
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum RuskfileDeserializeError {
-    #[error("Task {0} is duplicated")]
-    DuplicatedTaskName(TaskKey),
+    #[error("Duplicated tasks found:{}", fmt_duplicates(.0))]
+    DuplicatedTaskNames(Vec<(TaskKey, Vec<TaskLocation>)>),
     #[error("Failed to convert Task: {0}")]
     DeserializeError(#[from] toml::de::Error),
 }
@@ -311,39 +663,122 @@ pub enum RuskfileDeserializeError {
 impl TryFrom<RuskfileComposer> for HashMap<TaskKey, Task> {
     type Error = RuskfileDeserializeError;
     fn try_from(composer: RuskfileComposer) -> Result<Self, Self::Error> {
-        let RuskfileComposer { map } = composer;
+        composer.compose(&HashMap::new())
+    }
+}
+
+impl RuskfileComposer {
+    /// Compose the tasks of all rusk.toml files.
+    /// - `overrides` take precedence over the `[vars]` of each file.
+    pub fn compose(
+        self,
+        overrides: &HashMap<String, String>,
+    ) -> Result<HashMap<TaskKey, Task>, RuskfileDeserializeError> {
+        let shadowed: HashSet<_> = self
+            .shadowed()
+            .into_iter()
+            .map(|(path, key)| (path.clone(), key.clone()))
+            .collect();
+        let RuskfileComposer { map, .. } = self;
         let mut tasks = HashMap::new();
+        let mut duplicates: HashMap<TaskKey, Vec<TaskLocation>> = HashMap::new();
         for (path, res) in map {
             let Ok(config) = res else {
                 continue;
             };
-            let configfile_dir = path.into_parent().unwrap(); // NOTE: path is guaranteed to be a NormalizedPath of an existing file, so it should have a parent directory
-            for (key, TaskDeserializer { inner, .. }) in config.tasks {
+            let configfile_dir = path.clone().into_parent().unwrap(); // NOTE: path is guaranteed to be a NormalizedPath of an existing file, so it should have a parent directory
+            let mut vars = config.vars;
+            vars.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+            let file_envs: HashMap<OsString, EnvValue> = config
+                .envs
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.interpolate(&vars).into()))
+                .collect();
+            for (key, task) in config.tasks {
+                if shadowed.contains(&(path.clone(), key.clone())) {
+                    continue;
+                }
+                let source = TaskLocation {
+                    path: path.clone(),
+                    line: line_of(&config.line_starts, task.span().start),
+                };
+                let TaskDeserializer { inner, .. } = task.into_inner();
                 let key = key.into_task_key(&configfile_dir);
                 let TaskDeserializerInner {
                     envs,
                     script,
                     depends,
                     cwd,
+                    deprecated,
+                    confirm,
+                    required_envs,
+                    service,
+                    ready,
+                    ready_check,
+                    outputs,
+                    priority,
+                    sandbox,
+                    allow_failure,
+                    success_codes,
                 } = inner.try_into()?; // NOTE: It is guaranteed to be a table, and fields that are not present will have default values.
+                let success_codes = success_codes.unwrap_or_else(|| vec![0]);
                 match tasks.entry_ref(&key) {
-                    EntryRef::Occupied(_) => {
-                        return Err(RuskfileDeserializeError::DuplicatedTaskName(key));
+                    EntryRef::Occupied(e) => {
+                        let first: &Task = e.get();
+                        duplicates
+                            .entry(key)
+                            .or_insert_with(|| vec![first.source.clone()])
+                            .push(source);
                     }
                     EntryRef::Vacant(e) => {
                         e.insert(Task {
-                            envs,
-                            script,
-                            cwd: configfile_dir.join(cwd.as_ref()).into(),
+                            envs: envs
+                                .into_iter()
+                                .map(|(name, value)| (name.into(), value.interpolate(&vars).into()))
+                                .collect(),
+                            file_envs: file_envs.clone(),
+                            source,
+                            script: script.map(|script| interpolate(&script, &vars)),
+                            cwd: configfile_dir.join(interpolate(&cwd, &vars)).into(),
                             depends: depends
                                 .into_iter()
                                 .map(|key| key.into_task_key(&configfile_dir))
                                 .collect(),
+                            deprecated,
+                            confirm,
+                            required_envs,
+                            service: service.then(|| ServiceConfig {
+                                ready,
+                                ready_check,
+                                success_codes: success_codes.clone(),
+                                allow_failure,
+                            }),
+                            outputs: outputs
+                                .into_iter()
+                                .map(|output| configfile_dir.join(output).into())
+                                .collect(),
+                            priority,
+                            sandbox: sandbox.map(|sandbox| sandbox.into_config(&configfile_dir)),
+                            allow_failure,
+                            success_codes,
                         });
                     }
                 }
             }
         }
+        if !duplicates.is_empty() {
+            let duplicates = duplicates
+                .into_iter()
+                .map(|(key, mut locations)| {
+                    locations.sort_by(|a, b| {
+                        (a.path.as_abs_str(), a.line).cmp(&(b.path.as_abs_str(), b.line))
+                    });
+                    (key, locations)
+                })
+                .sorted_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()))
+                .collect();
+            return Err(RuskfileDeserializeError::DuplicatedTaskNames(duplicates));
+        }
         Ok(tasks)
     }
 }
@@ -353,7 +788,70 @@ impl TryFrom<RuskfileComposer> for HashMap<TaskKey, Task> {
 struct RuskfileDeserializer {
     /// TaskDeserializers map
     #[serde(default)]
-    tasks: HashMap<TaskKeyRelative, TaskDeserializer>,
+    tasks: HashMap<TaskKeyRelative, Spanned<TaskDeserializer>>,
+    /// Environment variables shared by all tasks in the file
+    #[serde(default)]
+    envs: HashMap<String, EnvValueDeserializer>,
+    /// Variables interpolated as `{{name}}` into the scripts, cwd and envs of the file
+    #[serde(default)]
+    vars: HashMap<String, String>,
+    /// Byte offsets where each line of the file starts
+    #[serde(skip)]
+    line_starts: Vec<usize>,
+}
+
+impl RuskfileDeserializer {
+    /// Load the content of rusk.toml, or of the file of another task runner if `adapter` is given
+    fn load(content: &str, adapter: Option<Adapter>) -> Result<Self, RuskfileLoadError> {
+        let mut config = match adapter {
+            None => toml::from_str::<Self>(content).map_err(|err| RuskfileLoadError {
+                message: err.to_string(),
+                reason: err.message().to_owned(),
+                span: err.span(),
+            })?,
+            Some(adapter) => {
+                let tasks = adapter.tasks(content).map_err(|err| RuskfileLoadError {
+                    message: err.clone(),
+                    reason: err,
+                    span: None,
+                })?;
+                Self::imported(tasks)
+            }
+        };
+        config.line_starts = line_starts(content);
+        Ok(config)
+    }
+
+    /// Expose the imported tasks as phony tasks running the original task runner
+    fn imported(imported: Vec<ImportedTask>) -> Self {
+        let mut tasks = HashMap::new();
+        for task in imported {
+            // NOTE: Names which can't be a task name are skipped
+            let Ok(key) = TaskKeyRelative::try_from(task.key) else {
+                continue;
+            };
+            let mut inner = Table::new();
+            inner.insert("script".to_owned(), task.script.into());
+            let description = (!task.description.is_empty()).then_some(task.description);
+            // A target may be written in several rules; the first one is taken
+            tasks.entry(key).or_insert_with(|| {
+                Spanned::new(
+                    task.span,
+                    TaskDeserializer {
+                        inner,
+                        description,
+                        hidden: false,
+                    },
+                )
+            });
+        }
+        Self {
+            tasks,
+            envs: HashMap::new(),
+            vars: HashMap::new(),
+            line_starts: Vec::new(),
+        }
+    }
 }
 
 /// serde::Deserialize of Each rusk Task
@@ -365,13 +863,16 @@ struct TaskDeserializer {
     /// Description for help
     #[serde(default)]
     description: Option<String>,
+    /// Whether to hide from the tasks list
+    #[serde(default)]
+    hidden: bool,
 }
 
 #[derive(serde::Deserialize)]
 struct TaskDeserializerInner {
     /// Environment variables that are specific to this task
     #[serde(default)]
-    envs: HashMap<OsString, OsString>,
+    envs: HashMap<String, EnvValueDeserializer>,
     /// Script to be executed
     #[serde(default)]
     script: Option<String>,
@@ -381,6 +882,112 @@ struct TaskDeserializerInner {
     /// Working directory
     #[serde(default)]
     cwd: Cow<'static, str>,
+    /// Deprecation message shown as a warning when executed
+    #[serde(default)]
+    deprecated: Option<String>,
+    /// Confirmation message prompted before executing
+    #[serde(default)]
+    confirm: Option<String>,
+    /// Environment variables which must be set before executing
+    #[serde(default)]
+    required_envs: Vec<String>,
+    /// Whether the task is a long-running service
+    #[serde(default)]
+    service: bool,
+    /// Condition to regard the service as started
+    #[serde(default)]
+    ready: Option<ReadyCondition>,
+    /// Health check of the service
+    #[serde(default)]
+    ready_check: Option<ReadyCheck>,
+    /// Files generated by this task other than the task itself
+    #[serde(default)]
+    outputs: Vec<String>,
+    /// Scheduling priority under the jobs limit
+    #[serde(default)]
+    priority: i64,
+    /// Restrictions on the script execution
+    #[serde(default)]
+    sandbox: Option<SandboxDeserializer>,
+    /// Whether the run goes on even if the script fails
+    #[serde(default)]
+    allow_failure: bool,
+    /// Exit codes regarded as success
+    #[serde(default)]
+    success_codes: Option<Vec<i32>>,
+}
+
+/// serde::Deserialize of Environment variable value
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum EnvValueDeserializer {
+    /// Literal value
+    Static(String),
+    /// Value computed from the stdout of the command
+    Command { cmd: String },
+}
+
+impl EnvValueDeserializer {
+    /// Interpolate the variables into the value or the command
+    fn interpolate(self, vars: &HashMap<String, String>) -> Self {
+        match self {
+            EnvValueDeserializer::Static(value) => {
+                EnvValueDeserializer::Static(interpolate(&value, vars))
+            }
+            EnvValueDeserializer::Command { cmd } => EnvValueDeserializer::Command {
+                cmd: interpolate(&cmd, vars),
+            },
+        }
+    }
+}
+
+impl From<EnvValueDeserializer> for EnvValue {
+    fn from(value: EnvValueDeserializer) -> Self {
+        match value {
+            EnvValueDeserializer::Static(value) => EnvValue::Static(value.into()),
+            EnvValueDeserializer::Command { cmd } => EnvValue::Command(cmd),
+        }
+    }
+}
+
+/// serde::Deserialize of the sandbox restrictions
+#[derive(serde::Deserialize)]
+struct SandboxDeserializer {
+    /// Whether to allow the network access
+    #[serde(default)]
+    network: bool,
+    /// Paths allowed to read and execute
+    #[serde(default = "SandboxDeserializer::default_read_only")]
+    read_only: Vec<String>,
+    /// Paths allowed to read and write
+    #[serde(default)]
+    write: Vec<String>,
+}
+
+impl SandboxDeserializer {
+    fn default_read_only() -> Vec<String> {
+        vec!["/".to_owned()]
+    }
+
+    /// Resolve the paths relative to the directory of the ruskfile
+    fn into_config(self, dir: &Path) -> SandboxConfig {
+        SandboxConfig {
+            network: self.network,
+            read_only: self
+                .read_only
+                .into_iter()
+                .map(|path| dir.join(path).into())
+                .collect(),
+            write: self
+                .write
+                .into_iter()
+                .map(|path| WritePath {
+                    dir: path.ends_with('/'),
+                    path: dir.join(path).into(),
+                })
+                .collect(),
+        }
+    }
 }
 
 impl Default for TaskDeserializerInner {
@@ -390,6 +997,145 @@ impl Default for TaskDeserializerInner {
             script: Default::default(),
             depends: Default::default(),
             cwd: Cow::Borrowed("."),
+            deprecated: Default::default(),
+            confirm: Default::default(),
+            required_envs: Default::default(),
+            service: Default::default(),
+            ready: Default::default(),
+            ready_check: Default::default(),
+            outputs: Default::default(),
+            priority: Default::default(),
+            sandbox: Default::default(),
+            allow_failure: Default::default(),
+            success_codes: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn interpolate_defined_vars() {
+        let vars = vars(&[("out", "dist"), ("mode", "release")]);
+        assert_eq!(interpolate("{{out}}/{{ mode }}", &vars), "dist/release");
+        assert_eq!(interpolate("{{out}}{{out}}", &vars), "distdist");
+    }
+
+    #[test]
+    fn interpolate_leaves_others() {
+        let vars = vars(&[("out", "dist"), ("nested", "{{out}}")]);
+        assert_eq!(
+            interpolate("{{undefined}} {{out}}", &vars),
+            "{{undefined}} dist"
+        );
+        assert_eq!(interpolate("${{ github.sha }}", &vars), "${{ github.sha }}");
+        assert_eq!(interpolate("{{out", &vars), "{{out");
+        assert_eq!(interpolate("{{}} }}", &vars), "{{}} }}");
+        // Values are not interpolated again
+        assert_eq!(interpolate("{{nested}}", &vars), "{{out}}");
+    }
+
+    /// Composer of the files, with all the imports enabled
+    fn composer(files: &[(&str, &str)]) -> RuskfileComposer {
+        let imports = Imports {
+            npm: true,
+            make: true,
+        };
+        let mut composer = RuskfileComposer::new().with_imports(imports);
+        for (path, content) in files {
+            composer.insert_source(path, content);
         }
+        composer
+    }
+
+    #[test]
+    fn listed_cwd_interpolates_vars() {
+        let composer = composer(&[(
+            "/project/rusk.toml",
+            r#"
+            vars = { out = "build" }
+            tasks.main = { cwd = "{{out}}/bin", script = "ls" }
+            "#,
+        )]);
+        let cwd = composer
+            .tasks_list(true)
+            .next()
+            .unwrap()
+            .content()
+            .unwrap()
+            .cwd();
+        assert_eq!(cwd.as_abs_str(), "/project/build/bin");
+
+        let tasks = composer.compose(&vars(&[("out", "dist")])).unwrap();
+        let (_, task) = tasks.iter().next().unwrap();
+        assert_eq!(task.cwd.as_abs_str(), "/project/dist/bin");
+    }
+
+    const PACKAGE_JSON: &str = r#"{ "scripts": { "test": "jest", "lint": "eslint" } }"#;
+
+    fn listed(composer: &RuskfileComposer) -> Vec<String> {
+        composer
+            .tasks_list(true)
+            .map(|item| {
+                format!(
+                    "{:#} {}",
+                    item.content().unwrap().key(),
+                    item.path().as_abs_str()
+                )
+            })
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn nearer_import_shadows() {
+        let composer = composer(&[
+            ("/project/b/c/package.json", PACKAGE_JSON),
+            ("/project/a/package.json", PACKAGE_JSON),
+            ("/project/d/package.json", PACKAGE_JSON),
+        ]);
+        assert_eq!(
+            listed(&composer),
+            [
+                "npm:lint /project/a/package.json",
+                "npm:test /project/a/package.json"
+            ]
+        );
+        let tasks = composer.compose(&HashMap::new()).unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert!(
+            tasks
+                .values()
+                .all(|task| task.cwd.as_abs_str() == "/project/a")
+        );
+    }
+
+    #[test]
+    fn ruskfile_shadows_import() {
+        let composer = composer(&[
+            ("/project/a/package.json", PACKAGE_JSON),
+            (
+                "/project/a/b/rusk.toml",
+                r#"tasks."npm:test" = { script = "npm test -- --ci" }"#,
+            ),
+        ]);
+        assert_eq!(
+            listed(&composer),
+            [
+                "npm:lint /project/a/package.json",
+                "npm:test /project/a/b/rusk.toml"
+            ]
+        );
+        let tasks = composer.compose(&HashMap::new()).unwrap();
+        let script = tasks.values().filter_map(|task| task.script.as_deref());
+        assert!(script.sorted().eq(["npm run 'lint'", "npm test -- --ci"]));
     }
 }
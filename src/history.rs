@@ -0,0 +1,205 @@
+//! Cross-run execution history of tasks.
+
+use std::{
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+use colored::Colorize;
+use futures::{FutureExt, future::LocalBoxFuture};
+use tokio::io::AsyncWriteExt;
+
+use crate::taskkey::TaskKey;
+
+/// Directory to store the data of rusk, placed next to the ruskfile
+pub const DATA_DIR: &str = ".rusk";
+
+/// File name of the history in [DATA_DIR]
+const HISTORY_FILE: &str = "history";
+
+/// Storage of the history files
+pub trait HistoryBackend {
+    /// Content of the history file. Empty if it doesn't exist.
+    fn read<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, io::Result<String>>;
+    /// Append the line to the history file, creating it if needed
+    fn append<'a>(&'a self, path: &'a Path, line: &'a str) -> LocalBoxFuture<'a, io::Result<()>>;
+}
+
+/// History files on the disk
+pub struct DiskHistory;
+
+impl HistoryBackend for DiskHistory {
+    fn read<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, io::Result<String>> {
+        async move {
+            match tokio::fs::read_to_string(path).await {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+                res => res,
+            }
+        }
+        .boxed_local()
+    }
+
+    fn append<'a>(&'a self, path: &'a Path, line: &'a str) -> LocalBoxFuture<'a, io::Result<()>> {
+        async move {
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+            file.write_all(line.as_bytes()).await
+        }
+        .boxed_local()
+    }
+}
+
+/// Record of one execution
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    /// When the execution started
+    pub timestamp: SystemTime,
+    /// How long the execution took
+    pub duration: Duration,
+    /// Exit code of the script
+    pub exit_code: i32,
+}
+
+/// History of the tasks defined in the ruskfiles of one directory.
+/// - Stored as lines of `name\ttimestamp\tduration_ms\texit_code` in `.rusk/history`.
+#[derive(Clone)]
+pub struct HistoryStore {
+    /// Path to the history file
+    path: PathBuf,
+    /// Name of the task in the history
+    name: String,
+    /// Storage of the history file
+    backend: Rc<dyn HistoryBackend>,
+}
+
+impl HistoryStore {
+    /// Create the store of the task defined in the directory
+    pub fn new(dir: &Path, key: &TaskKey, backend: Rc<dyn HistoryBackend>) -> Self {
+        let name = match key {
+            TaskKey::Phony(name) => name.as_ref().to_owned(),
+            TaskKey::File(path) => pathdiff::diff_paths(path.as_abs_str(), dir)
+                .and_then(|rel| rel.into_os_string().into_string().ok())
+                .unwrap_or_else(|| path.as_abs_str().to_owned()),
+        };
+        Self {
+            path: dir.join(DATA_DIR).join(HISTORY_FILE),
+            name,
+            backend,
+        }
+    }
+
+    /// Append the entry to the history
+    pub async fn append(&self, entry: &HistoryEntry) -> io::Result<()> {
+        let timestamp = entry
+            .timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let line = format!(
+            "{}\t{}\t{}\t{}\n",
+            self.name,
+            timestamp,
+            entry.duration.as_millis(),
+            entry.exit_code
+        );
+        self.backend.append(&self.path, &line).await
+    }
+
+    /// Read all entries of the task, oldest first
+    pub async fn entries(&self) -> io::Result<Vec<HistoryEntry>> {
+        let content = self.backend.read(&self.path).await?;
+        Ok(content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                if fields.next()? != self.name {
+                    return None;
+                }
+                let timestamp = fields.next()?.parse().ok()?;
+                let duration = fields.next()?.parse().ok()?;
+                let exit_code = fields.next()?.parse().ok()?;
+                Some(HistoryEntry {
+                    timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp),
+                    duration: Duration::from_millis(duration),
+                    exit_code,
+                })
+            })
+            .collect())
+    }
+
+    /// Read the statistics of the task
+    pub async fn stats(&self) -> io::Result<TaskStats> {
+        Ok(TaskStats::from(self.entries().await?))
+    }
+}
+
+/// Statistics of the executions of a task
+#[derive(Debug, Clone)]
+pub struct TaskStats {
+    /// Number of the executions
+    pub runs: usize,
+    /// The last execution
+    pub last: Option<HistoryEntry>,
+    /// Average duration of the executions
+    pub average_duration: Option<Duration>,
+    /// Ratio of the failed executions
+    pub failure_rate: Option<f64>,
+}
+
+impl From<Vec<HistoryEntry>> for TaskStats {
+    fn from(entries: Vec<HistoryEntry>) -> Self {
+        let runs = entries.len();
+        let (average_duration, failure_rate) = if runs == 0 {
+            (None, None)
+        } else {
+            let total: Duration = entries.iter().map(|entry| entry.duration).sum();
+            let failures = entries.iter().filter(|entry| entry.exit_code != 0).count();
+            (
+                Some(total / runs as u32),
+                Some(failures as f64 / runs as f64),
+            )
+        };
+        Self {
+            runs,
+            last: entries.into_iter().last(),
+            average_duration,
+            failure_rate,
+        }
+    }
+}
+
+impl Display for TaskStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(last) = &self.last else {
+            return "no runs recorded".dimmed().fmt(f);
+        };
+        let ago = SystemTime::now()
+            .duration_since(last.timestamp)
+            .unwrap_or_default()
+            .as_secs();
+        writeln!(f, "{} {}", "runs".dimmed(), self.runs)?;
+        writeln!(
+            f,
+            "{} {ago}s ago, took {:?}, exit code {}",
+            "last".dimmed(),
+            last.duration,
+            last.exit_code
+        )?;
+        if let Some(average) = self.average_duration {
+            writeln!(f, "{} {:?}", "average".dimmed(), average)?;
+        }
+        if let Some(rate) = self.failure_rate {
+            write!(f, "{} {:.1}%", "failure rate".dimmed(), rate * 100.0)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+//! Adapters exposing the tasks of other task runners as phony tasks.
+
+use std::{ffi::OsStr, ops::Range, str::CharIndices};
+
+/// Namespaces of the imported tasks, written as `(namespace):(name)`
+pub const NAMESPACES: &[&str] = &["npm", "make"];
+
+/// Task runners to import the tasks from
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Imports {
+    /// Scripts of package.json
+    pub npm: bool,
+    /// Top-level targets of Makefiles
+    pub make: bool,
+}
+
+impl Imports {
+    /// Enable the adapter by its namespace. Returns false if unknown.
+    pub fn enable(&mut self, namespace: &str) -> bool {
+        match namespace {
+            "npm" => self.npm = true,
+            "make" => self.make = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Adapter to read the file, if it is enabled
+    pub fn adapter(&self, name: &OsStr) -> Option<Adapter> {
+        match name.to_str()? {
+            "package.json" if self.npm => Some(Adapter::Npm),
+            "Makefile" | "makefile" | "GNUmakefile" if self.make => Some(Adapter::Make),
+            _ => None,
+        }
+    }
+}
+
+/// File of another task runner
+#[derive(Debug, Clone, Copy)]
+pub enum Adapter {
+    /// package.json
+    Npm,
+    /// Makefile
+    Make,
+}
+
+/// Task read from the file of another task runner
+pub struct ImportedTask {
+    /// Task name with the namespace
+    pub key: String,
+    /// Script to run the task with the original runner
+    pub script: String,
+    /// Description shown in the tasks list
+    pub description: String,
+    /// Byte range where the task is defined
+    pub span: Range<usize>,
+}
+
+impl Adapter {
+    /// Read the tasks from the content of the file
+    pub fn tasks(self, content: &str) -> Result<Vec<ImportedTask>, String> {
+        match self {
+            Adapter::Npm => npm_tasks(content),
+            Adapter::Make => Ok(make_tasks(content)),
+        }
+    }
+}
+
+/// Scripts of package.json
+fn npm_tasks(content: &str) -> Result<Vec<ImportedTask>, String> {
+    let mut tasks = Vec::new();
+    let mut reader = JsonReader {
+        src: content,
+        pos: 0,
+    };
+    reader.object(|reader, key, _| {
+        if key != "scripts" {
+            return reader.skip_value();
+        }
+        reader.object(|reader, name, start| {
+            if reader.peek() != Some(b'"') {
+                return reader.skip_value();
+            }
+            let command = reader.string()?;
+            // NOTE: Names which can't be quoted are skipped
+            if !name.contains(['\'', '\n']) {
+                tasks.push(ImportedTask {
+                    key: format!("npm:{name}"),
+                    script: format!("npm run '{name}'"),
+                    description: command,
+                    span: start..reader.pos,
+                });
+            }
+            Ok(())
+        })
+    })?;
+    Ok(tasks)
+}
+
+/// Top-level targets of the Makefile.
+/// - Special targets, pattern rules and variable assignments are skipped.
+/// - A trailing `## (text)` comment on the rule is used as the description.
+fn make_tasks(content: &str) -> Vec<ImportedTask> {
+    let mut tasks = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        if line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '.') {
+            continue;
+        }
+        let Some((targets, rest)) = line.split_once(':') else {
+            continue;
+        };
+        if rest.starts_with('=') || rest.starts_with(":=") || targets.contains('=') {
+            continue; // Variable assignment
+        }
+        let description = rest
+            .split_once("##")
+            .map(|(_, comment)| comment.trim().to_owned())
+            .unwrap_or_default();
+        for target in targets.split_whitespace() {
+            let valid = target
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'));
+            if !valid {
+                continue;
+            }
+            tasks.push(ImportedTask {
+                key: format!("make:{target}"),
+                script: format!("make {target}"),
+                description: description.clone(),
+                span: start..start + line.trim_end().len(),
+            });
+        }
+    }
+    tasks
+}
+
+/// Minimal JSON reader to pick the scripts of package.json
+struct JsonReader<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl JsonReader<'_> {
+    /// Skip the whitespaces and peek the next byte
+    fn peek(&mut self) -> Option<u8> {
+        let rest = &self.src[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    /// Consume the byte
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!(
+                "Expected {:?} at byte {} of JSON",
+                byte as char, self.pos
+            ))
+        }
+    }
+
+    /// Read the string
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut value = String::new();
+        let mut chars = self.src[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(value);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let mut code = hex4(&mut chars);
+                            // Characters outside the BMP are written as the surrogate pair
+                            if let Some(high @ 0xd800..=0xdbff) = code {
+                                let mut lookahead = chars.clone();
+                                if lookahead.next().map(|(_, c)| c) == Some('\\')
+                                    && lookahead.next().map(|(_, c)| c) == Some('u')
+                                    && let Some(low @ 0xdc00..=0xdfff) = hex4(&mut lookahead)
+                                {
+                                    chars = lookahead;
+                                    code = Some(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00));
+                                }
+                            }
+                            code.and_then(char::from_u32)
+                                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        Some(c) => c,
+                        None => break,
+                    };
+                    value.push(escaped);
+                }
+                c => value.push(c),
+            }
+        }
+        Err("Unterminated string in JSON".to_owned())
+    }
+
+    /// Read the object, calling `entry` with each key and its start offset to consume the value
+    fn object(
+        &mut self,
+        mut entry: impl FnMut(&mut Self, String, usize) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.expect(b'{')?;
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            self.peek();
+            let start = self.pos;
+            let key = self.string()?;
+            self.expect(b':')?;
+            entry(self, key, start)?;
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                _ => return self.expect(b'}'),
+            }
+        }
+    }
+
+    /// Skip the value
+    fn skip_value(&mut self) -> Result<(), String> {
+        match self.peek() {
+            Some(b'"') => self.string().map(drop),
+            Some(b'{') => self.object(|reader, _, _| reader.skip_value()),
+            Some(b'[') => {
+                self.pos += 1;
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                loop {
+                    self.skip_value()?;
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        _ => return self.expect(b']'),
+                    }
+                }
+            }
+            Some(_) => {
+                // Number, true, false or null
+                let rest = &self.src[self.pos..];
+                let len = rest
+                    .find(|c: char| matches!(c, ',' | '}' | ']') || c.is_whitespace())
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(format!("Unexpected character at byte {} of JSON", self.pos));
+                }
+                self.pos += len;
+                Ok(())
+            }
+            None => Err("Unexpected end of JSON".to_owned()),
+        }
+    }
+}
+
+/// Read the 4 hex digits of `\uXXXX`
+fn hex4(chars: &mut CharIndices) -> Option<u32> {
+    let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+    if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tasks(tasks: Vec<ImportedTask>) -> Vec<(String, String, String)> {
+        tasks
+            .into_iter()
+            .map(|task| (task.key, task.script, task.description))
+            .collect()
+    }
+
+    fn task(key: &str, script: &str, description: &str) -> (String, String, String) {
+        (key.to_owned(), script.to_owned(), description.to_owned())
+    }
+
+    #[test]
+    fn npm_scripts() {
+        let content = r#"{
+            "name": "app",
+            "version": 1.0,
+            "private": true,
+            "workspaces": ["a", { "b": null }],
+            "config": { "scripts": { "nested": "ignored" } },
+            "scripts": {
+                "build": "tsc -p .",
+                "test:unit": "jest \"src\"",
+                "it's": "skipped",
+                "disabled": null
+            }
+        }"#;
+        assert_eq!(
+            tasks(npm_tasks(content).unwrap()),
+            [
+                task("npm:build", "npm run 'build'", "tsc -p ."),
+                task("npm:test:unit", "npm run 'test:unit'", "jest \"src\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn npm_script_span() {
+        let content = "{\"scripts\": {\"build\": \"tsc\"}}";
+        let span = npm_tasks(content).unwrap().remove(0).span;
+        assert_eq!(&content[span], "\"build\": \"tsc\"");
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        let mut reader = JsonReader {
+            src: r#""a\n\t\"\\\/\u00e9\ud83d\ude00\ud800x""#,
+            pos: 0,
+        };
+        assert_eq!(reader.string().unwrap(), "a\n\t\"\\/é😀\u{fffd}x");
+    }
+
+    #[test]
+    fn json_errors() {
+        for content in [
+            "",
+            "[]",
+            r#"{"scripts": {"build": "tsc"}"#,
+            r#"{"scripts": {"build": "tsc}}"#,
+            r#"{"scripts" {}}"#,
+            r#"{"a": }"#,
+        ] {
+            assert!(npm_tasks(content).is_err(), "{content:?}");
+        }
+        assert!(npm_tasks("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn make_targets() {
+        let content = "\
+CC := gcc
+FLAGS = -O2
+NAME ::= app
+.PHONY: build test
+build: src/main.c ## Build the app
+\t$(CC) $(FLAGS) -o $(NAME) src/main.c
+%.o: %.c
+\t$(CC) -c $<
+# comment: not a target
+test lint: build
+\techo test: done
+out/app.bin: build
+$(NAME): build
+install:: build
+";
+        assert_eq!(
+            tasks(make_tasks(content)),
+            [
+                task("make:build", "make build", "Build the app"),
+                task("make:test", "make test", ""),
+                task("make:lint", "make lint", ""),
+                task("make:out/app.bin", "make out/app.bin", ""),
+                task("make:install", "make install", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn make_target_span() {
+        let content = "all: build\r\nbuild: ## Build\r\n";
+        let span = make_tasks(content).remove(1).span;
+        assert_eq!(&content[span], "build: ## Build");
+    }
+}
@@ -0,0 +1,211 @@
+//! Pinning of the discovered ruskfiles for reproducible runs.
+
+use std::{collections::BTreeMap, fmt::Display, io, path::Path};
+
+use colored::Colorize;
+use itertools::Itertools;
+use sha2::{Digest, Sha256};
+
+/// File name of the lockfile, placed in the directory where rusk is run
+pub const LOCK_FILE: &str = "rusk.lock";
+
+/// Hex-encoded SHA-256 of the content
+pub fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Set of the ruskfiles and their content hashes.
+/// - Stored as lines of `hash  path`, where the path is relative to the directory of the lockfile.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Lockfile {
+    /// Content hashes keyed by the relative path of the ruskfile
+    files: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    /// Add the ruskfile to the lockfile
+    pub fn insert(&mut self, root: &Path, path: &Path, hash: String) {
+        let rel = pathdiff::diff_paths(path, root).unwrap_or_else(|| path.to_path_buf());
+        let rel = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .join("/");
+        self.files.insert(rel, hash);
+    }
+
+    /// Read the lockfile in the directory
+    pub async fn read(root: &Path) -> Result<Self, LockError> {
+        let content = match tokio::fs::read_to_string(root.join(LOCK_FILE)).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(LockError::NotFound),
+            Err(err) => return Err(LockError::Io(err)),
+        };
+        Self::parse(&content)
+    }
+
+    /// Parse the content of the lockfile
+    fn parse(content: &str) -> Result<Self, LockError> {
+        let mut files = BTreeMap::new();
+        for (i, line) in content.lines().enumerate() {
+            let Some((hash, path)) = line.split_once("  ") else {
+                return Err(LockError::Malformed { line: i + 1 });
+            };
+            files.insert(path.to_owned(), hash.to_owned());
+        }
+        Ok(Self { files })
+    }
+
+    /// Write the lockfile to the directory
+    pub async fn write(&self, root: &Path) -> Result<(), LockError> {
+        tokio::fs::write(root.join(LOCK_FILE), self.to_string())
+            .await
+            .map_err(LockError::Io)
+    }
+
+    /// Verify the ruskfiles against the pinned ones
+    pub fn verify(&self, current: &Lockfile) -> Result<(), LockError> {
+        let mut diffs = Vec::new();
+        for (path, hash) in &self.files {
+            match current.files.get(path) {
+                None => diffs.push(LockDiff::Removed(path.clone())),
+                Some(current) if current != hash => diffs.push(LockDiff::Changed(path.clone())),
+                _ => {}
+            }
+        }
+        for path in current.files.keys() {
+            if !self.files.contains_key(path) {
+                diffs.push(LockDiff::Added(path.clone()));
+            }
+        }
+        if diffs.is_empty() {
+            Ok(())
+        } else {
+            diffs.sort_by(|a, b| a.path().cmp(b.path()));
+            Err(LockError::Mismatch(diffs))
+        }
+    }
+}
+
+impl Display for Lockfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (path, hash) in &self.files {
+            writeln!(f, "{hash}  {path}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Difference between the pinned and the current ruskfiles
+#[derive(Debug)]
+pub enum LockDiff {
+    /// Ruskfile not pinned in the lockfile
+    Added(String),
+    /// Pinned ruskfile no longer found
+    Removed(String),
+    /// Content of the ruskfile changed
+    Changed(String),
+}
+
+impl LockDiff {
+    /// Relative path of the ruskfile
+    fn path(&self) -> &str {
+        match self {
+            LockDiff::Added(path) | LockDiff::Removed(path) | LockDiff::Changed(path) => path,
+        }
+    }
+}
+
+impl Display for LockDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockDiff::Added(path) => write!(f, "{} {path}", "added".green()),
+            LockDiff::Removed(path) => write!(f, "{} {path}", "removed".red()),
+            LockDiff::Changed(path) => write!(f, "{} {path}", "changed".yellow()),
+        }
+    }
+}
+
+/// Error of the lockfile
+#[derive(Debug, thiserror::Error)]
+pub enum LockError {
+    /// Lockfile does not exist
+    #[error("{LOCK_FILE} not found. Run with --lock to create it.")]
+    NotFound,
+    /// Lockfile has a broken line
+    #[error("{LOCK_FILE} is malformed at line {line}")]
+    Malformed { line: usize },
+    /// Failed to access the lockfile
+    #[error("Failed to access {LOCK_FILE}: {0}")]
+    Io(io::Error),
+    /// Ruskfiles differ from the pinned ones
+    #[error("Ruskfiles differ from {LOCK_FILE}:\n{}", .0.iter().map(|diff| format!("  {diff}")).join("\n"))]
+    Mismatch(Vec<LockDiff>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lockfile(files: &[(&str, &str)]) -> Lockfile {
+        let root = Path::new("/project");
+        let mut lockfile = Lockfile::default();
+        for (path, content) in files {
+            lockfile.insert(root, &root.join(path), content_hash(content.as_bytes()));
+        }
+        lockfile
+    }
+
+    #[test]
+    fn insert_relative_path() {
+        let lockfile = lockfile(&[("sub/rusk.toml", "")]);
+        assert!(lockfile.files.contains_key("sub/rusk.toml"));
+    }
+
+    #[test]
+    fn verify_same_files() {
+        let pinned = lockfile(&[("rusk.toml", "a"), ("sub/rusk.toml", "b")]);
+        let current = lockfile(&[("sub/rusk.toml", "b"), ("rusk.toml", "a")]);
+        assert!(pinned.verify(&current).is_ok());
+    }
+
+    #[test]
+    fn verify_reports_sorted_diffs() {
+        let pinned = lockfile(&[
+            ("c.rusk.toml", "c"),
+            ("b.rusk.toml", "b"),
+            ("rusk.toml", ""),
+        ]);
+        let current = lockfile(&[
+            ("a.rusk.toml", "a"),
+            ("b.rusk.toml", "B"),
+            ("rusk.toml", ""),
+        ]);
+        let Err(LockError::Mismatch(diffs)) = pinned.verify(&current) else {
+            panic!("differences should be reported");
+        };
+        let diffs: Vec<_> = diffs
+            .iter()
+            .map(|diff| match diff {
+                LockDiff::Added(path) => format!("+{path}"),
+                LockDiff::Removed(path) => format!("-{path}"),
+                LockDiff::Changed(path) => format!("~{path}"),
+            })
+            .collect();
+        assert_eq!(diffs, ["+a.rusk.toml", "~b.rusk.toml", "-c.rusk.toml"]);
+    }
+
+    #[test]
+    fn parse_written_content() {
+        let written = lockfile(&[("rusk.toml", "a"), ("sub dir/rusk.toml", "b")]);
+        assert_eq!(Lockfile::parse(&written.to_string()).unwrap(), written);
+    }
+
+    #[test]
+    fn parse_malformed_line() {
+        let res = Lockfile::parse("0123  rusk.toml\nbroken\n");
+        assert!(matches!(res, Err(LockError::Malformed { line: 2 })));
+    }
+}
@@ -0,0 +1,490 @@
+//! Analysis of the ruskfiles for editor integrations such as a language server.
+
+use std::{fmt::Display, ops::Range};
+
+use hashbrown::{HashMap, HashSet};
+use itertools::Itertools;
+use toml::de::{DeTable, DeValue};
+
+use crate::{
+    fs::{RuskfileComposer, is_ruskfile, line_of, line_starts},
+    path::NormarizedPath,
+    taskkey::{TaskKey, TaskKeyRelative},
+    vfs::FsProvider,
+};
+
+/// Position in a ruskfile. Both are 1-origin, and the column is counted in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    /// Line number
+    pub line: usize,
+    /// Column number
+    pub column: usize,
+}
+
+impl Position {
+    /// Parse `(path):(line):(column)`
+    pub fn parse_location(location: &str) -> Option<(&str, Self)> {
+        let mut fields = location.rsplitn(3, ':');
+        let column = fields.next()?.parse().ok()?;
+        let line = fields.next()?.parse().ok()?;
+        let path = fields.next()?;
+        Some((path, Position { line, column }))
+    }
+}
+
+/// Range in a ruskfile
+#[derive(Debug, Clone)]
+pub struct Span {
+    /// Path to the ruskfile
+    pub path: NormarizedPath,
+    /// Start position
+    pub start: Position,
+    /// End position (exclusive)
+    pub end: Position,
+}
+
+impl Span {
+    /// Whether the position is in the range
+    fn contains(&self, path: &NormarizedPath, position: Position) -> bool {
+        &self.path == path && self.start <= position && position <= self.end
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.path.as_short_str(),
+            self.start.line,
+            self.start.column
+        )
+    }
+}
+
+/// Severity of a diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => "error".fmt(f),
+            Severity::Warning => "warning".fmt(f),
+        }
+    }
+}
+
+/// Problem found in a ruskfile
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// Where the problem is
+    pub span: Span,
+    /// Severity
+    pub severity: Severity,
+    /// Message
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.span, self.severity, self.message)
+    }
+}
+
+/// Ruskfile content to convert the byte offsets into positions
+struct Document<'a> {
+    /// Path to the ruskfile
+    path: &'a NormarizedPath,
+    /// Content
+    source: &'a str,
+    /// Byte offsets where each line starts
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Document<'a> {
+    fn new(path: &'a NormarizedPath, source: &'a str) -> Self {
+        Self {
+            path,
+            source,
+            line_starts: line_starts(source),
+        }
+    }
+
+    /// Position of the byte offset
+    fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.source.len());
+        let line = line_of(&self.line_starts, offset);
+        let line_start = self.line_starts[line - 1];
+        let column = self
+            .source
+            .get(line_start..offset)
+            .map_or(0, |s| s.chars().count())
+            + 1;
+        Position { line, column }
+    }
+
+    /// Span of the byte range
+    fn span(&self, range: Range<usize>) -> Span {
+        Span {
+            path: self.path.clone(),
+            start: self.position(range.start),
+            end: self.position(range.end),
+        }
+    }
+}
+
+/// Dependency written in `depends`
+struct Reference {
+    /// Task which depends on
+    from: TaskKey,
+    /// Task depended on, or the parse error message
+    to: Result<TaskKey, String>,
+    /// Where the dependency is written
+    span: Span,
+}
+
+/// Task definitions and dependencies of all the ruskfiles, with their spans
+pub struct Analysis {
+    /// Where each task is defined
+    definitions: HashMap<TaskKey, Vec<Span>>,
+    /// Dependencies
+    references: Vec<Reference>,
+    /// Errors found while loading the ruskfiles
+    errors: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    /// Analyze the ruskfiles loaded by the composer
+    pub fn new(composer: &RuskfileComposer) -> Self {
+        let mut analysis = Analysis {
+            definitions: HashMap::new(),
+            references: Vec::new(),
+            errors: Vec::new(),
+        };
+        for (path, source, load_error) in composer.sources() {
+            let Some(source) = source else {
+                let doc = Document::new(path, "");
+                analysis.errors.push(Diagnostic {
+                    span: doc.span(0..0),
+                    severity: Severity::Error,
+                    message: load_error.map_or_else(String::new, |err| err.reason().to_owned()),
+                });
+                continue;
+            };
+            let doc = Document::new(path, source);
+            if !is_ruskfile(path.file_name().unwrap_or_default()) {
+                if let Some(err) = load_error {
+                    analysis.errors.push(Diagnostic {
+                        span: doc.span(0..0),
+                        severity: Severity::Error,
+                        message: err.reason().to_owned(),
+                    });
+                }
+                analysis.collect_imported(composer, &doc);
+                continue;
+            }
+            let (table, errors) = DeTable::parse_recoverable(source);
+            for err in &errors {
+                analysis.errors.push(Diagnostic {
+                    span: doc.span(err.span().unwrap_or_default()),
+                    severity: Severity::Error,
+                    message: err.message().to_owned(),
+                });
+            }
+            if errors.is_empty()
+                && let Some(err) = load_error
+            {
+                analysis.errors.push(Diagnostic {
+                    span: doc.span(err.span().unwrap_or_default()),
+                    severity: Severity::Error,
+                    message: err.reason().to_owned(),
+                });
+            }
+            // NOTE: The recovered table may contain broken entries such as an unclosed header
+            if errors.is_empty() {
+                analysis.collect_tasks(&doc, table.get_ref());
+            }
+        }
+        analysis
+    }
+
+    /// Collect the task definitions and their dependencies in the document
+    fn collect_tasks(&mut self, doc: &Document, table: &DeTable) {
+        let Some(tasks) = table
+            .get("tasks")
+            .and_then(|tasks| tasks.get_ref().as_table())
+        else {
+            return;
+        };
+        let dir = doc.path.clone().into_parent().unwrap(); // NOTE: path is of an existing file
+        for (key, task) in tasks {
+            let span = doc.span(key.span());
+            // Invalid task names are reported as the load error
+            let Ok(key) = TaskKeyRelative::try_from(key.get_ref().to_string()) else {
+                continue;
+            };
+            let key = key.into_task_key(&dir);
+            self.definitions.entry(key.clone()).or_default().push(span);
+            let Some(depends) = task
+                .get_ref()
+                .get("depends")
+                .and_then(|depends| depends.get_ref().as_array())
+            else {
+                continue;
+            };
+            for dep in depends {
+                let to = match dep.get_ref() {
+                    DeValue::String(dep) => TaskKeyRelative::try_from(dep.to_string())
+                        .map(|dep| dep.into_task_key(&dir))
+                        .map_err(|err| format!("Invalid task name: {err}")),
+                    _ => Err("Dependency must be a string".to_owned()),
+                };
+                self.references.push(Reference {
+                    from: key.clone(),
+                    to,
+                    span: doc.span(dep.span()),
+                });
+            }
+        }
+    }
+
+    /// Collect the tasks imported from the file of another task runner
+    fn collect_imported(&mut self, composer: &RuskfileComposer, doc: &Document) {
+        for item in composer.tasks_list(true) {
+            if item.path() != doc.path {
+                continue;
+            }
+            if let Ok(content) = item.content() {
+                let start = doc.line_starts[content.line() - 1];
+                self.definitions
+                    .entry(content.key().clone())
+                    .or_default()
+                    .push(doc.span(start..start));
+            }
+        }
+    }
+
+    /// Problems in the ruskfiles: load errors, duplicated tasks, unknown dependencies and cycles
+    pub async fn diagnostics(&self, fs: &dyn FsProvider) -> Vec<Diagnostic> {
+        let mut diagnostics = self.errors.clone();
+        for (key, spans) in &self.definitions {
+            if spans.len() > 1 {
+                for span in spans {
+                    diagnostics.push(Diagnostic {
+                        span: span.clone(),
+                        severity: Severity::Error,
+                        message: format!("Duplicated task: {}", key.as_ref()),
+                    });
+                }
+            }
+        }
+        for reference in &self.references {
+            let (severity, message) = match &reference.to {
+                Err(message) => (Severity::Error, message.clone()),
+                Ok(key) if self.definitions.contains_key(key) => continue,
+                Ok(key @ TaskKey::Phony(_)) => {
+                    (Severity::Error, format!("Unknown task: {}", key.as_ref()))
+                }
+                Ok(key @ TaskKey::File(path)) => {
+                    if fs.exists(path).await {
+                        continue;
+                    }
+                    (
+                        Severity::Warning,
+                        format!("No task or file: {}", key.as_ref()),
+                    )
+                }
+            };
+            diagnostics.push(Diagnostic {
+                span: reference.span.clone(),
+                severity,
+                message,
+            });
+        }
+        for (reference, cycle) in self.cycles() {
+            diagnostics.push(Diagnostic {
+                span: reference.span.clone(),
+                severity: Severity::Error,
+                message: format!(
+                    "Circular dependency: {}",
+                    cycle.iter().map(|key| key.as_ref()).join(" -> ")
+                ),
+            });
+        }
+        diagnostics.sort_by(|a, b| {
+            (a.span.path.as_abs_str(), a.span.start).cmp(&(b.span.path.as_abs_str(), b.span.start))
+        });
+        diagnostics
+    }
+
+    /// Dependencies closing a cycle, with the tasks in the cycle
+    fn cycles(&self) -> Vec<(&Reference, Vec<&TaskKey>)> {
+        let mut graph: HashMap<&TaskKey, Vec<&Reference>> = HashMap::new();
+        for reference in &self.references {
+            graph.entry(&reference.from).or_default().push(reference);
+        }
+
+        fn visit<'a>(
+            key: &'a TaskKey,
+            graph: &HashMap<&'a TaskKey, Vec<&'a Reference>>,
+            stack: &mut Vec<&'a TaskKey>,
+            done: &mut HashSet<&'a TaskKey>,
+            found: &mut Vec<(&'a Reference, Vec<&'a TaskKey>)>,
+        ) {
+            if done.contains(key) {
+                return;
+            }
+            stack.push(key);
+            for &reference in graph.get(key).into_iter().flatten() {
+                let Ok(to) = &reference.to else {
+                    continue;
+                };
+                if let Some(i) = stack.iter().position(|&key| key == to) {
+                    let mut cycle = stack[i..].to_vec();
+                    cycle.push(to);
+                    found.push((reference, cycle));
+                } else {
+                    visit(to, graph, stack, done, found);
+                }
+            }
+            stack.pop();
+            done.insert(key);
+        }
+
+        let mut found = Vec::new();
+        let mut done = HashSet::new();
+        for &key in graph.keys().sorted_by(|a, b| a.as_ref().cmp(b.as_ref())) {
+            visit(key, &graph, &mut Vec::new(), &mut done, &mut found);
+        }
+        found
+    }
+
+    /// Definitions of the dependency at the position
+    pub fn definition(&self, path: &NormarizedPath, position: Position) -> Vec<Span> {
+        self.references
+            .iter()
+            .filter(|reference| reference.span.contains(path, position))
+            .filter_map(|reference| self.definitions.get(reference.to.as_ref().ok()?))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Task names as written in the ruskfile, to complete `depends` entries
+    pub fn completions(&self, path: &NormarizedPath) -> Vec<String> {
+        let dir = path.clone().into_parent().unwrap_or_else(|| path.clone());
+        self.definitions
+            .keys()
+            .filter_map(|key| match key {
+                TaskKey::Phony(_) => Some(key.as_ref().to_owned()),
+                TaskKey::File(file) => {
+                    let rel = pathdiff::diff_paths(file, &dir)?
+                        .into_os_string()
+                        .into_string()
+                        .ok()?;
+                    // A name without '/' or '.' would be a phony task
+                    if rel.contains('/') || rel.contains('.') {
+                        Some(rel)
+                    } else {
+                        Some(format!("./{rel}"))
+                    }
+                }
+            })
+            .sorted()
+            .dedup()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn analysis(source: &str) -> Analysis {
+        let mut composer = RuskfileComposer::new();
+        composer.insert_source("/project/rusk.toml", source);
+        Analysis::new(&composer)
+    }
+
+    fn path() -> NormarizedPath {
+        NormarizedPath::from(Path::new("/project/rusk.toml"))
+    }
+
+    fn at(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    #[test]
+    fn position_counts_characters() {
+        let path = path();
+        let doc = Document::new(&path, "[tasks.\"ビルド\"]\nscript = \"é\"");
+        let offset = doc.source.find(']').unwrap();
+        assert_eq!(doc.position(offset), at(1, 13));
+        // The last line without a newline
+        let end = doc.source.len();
+        assert_eq!(doc.position(end), at(2, 13));
+        assert_eq!(doc.position(end + 10), at(2, 13));
+    }
+
+    #[test]
+    fn self_loop_is_a_cycle() {
+        let analysis = analysis(r#"tasks.a = { depends = ["a"] }"#);
+        let cycles = analysis.cycles();
+        assert_eq!(cycles.len(), 1);
+        let names = cycles[0].1.iter().map(|key| key.as_ref()).collect_vec();
+        assert_eq!(names, ["a", "a"]);
+    }
+
+    #[test]
+    fn cycle_is_reported_once() {
+        let analysis = analysis(
+            r#"
+            tasks.a = { depends = ["b"] }
+            tasks.b = { depends = ["c"] }
+            tasks.c = { depends = ["a"] }
+            "#,
+        );
+        let cycles = analysis.cycles();
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].1.len(), 4);
+    }
+
+    #[test]
+    fn definition_of_dependency() {
+        let analysis = analysis("tasks.a = { depends = [\"b\"] }\ntasks.b = {}\n");
+        let spans = analysis.definition(&path(), at(1, 25));
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].start, spans[0].end), (at(2, 7), at(2, 8)));
+        assert!(analysis.definition(&path(), at(1, 3)).is_empty());
+    }
+
+    #[test]
+    fn completions_prefix_file_tasks() {
+        let analysis = analysis(
+            r#"
+            tasks.build = {}
+            tasks."file:out" = {}
+            tasks."dist/app.js" = {}
+            "#,
+        );
+        assert_eq!(
+            analysis.completions(&path()),
+            ["./out", "build", "dist/app.js"]
+        );
+    }
+
+    #[test]
+    fn broken_table_defines_nothing() {
+        let analysis = analysis("[tasks.bad\nscript = \"echo\"\n");
+        assert!(analysis.definitions.is_empty());
+        assert!(analysis.completions(&path()).is_empty());
+        assert!(!analysis.errors.is_empty());
+    }
+}
@@ -1,27 +1,51 @@
 use std::{
     fmt::Display,
     io::{BufWriter, IsTerminal, Write},
+    path::Path,
     time::Duration,
 };
 
-use args::Args;
+use args::{Args, ArgsError};
 use colored::Colorize;
 use fs::RuskfileComposer;
 use itertools::Itertools;
-use path::get_current_dir;
-use rusk::{Rusk, RuskError, TaskError};
+use lock::{LOCK_FILE, Lockfile};
+use lsp::{Analysis, Position, Severity};
+use path::{NormarizedPath, get_current_dir};
+use rusk::{ExecuteOpts, Rusk, RuskError, TaskError};
+use vfs::RealFs;
 
 mod args;
+mod bench;
+mod color;
 mod digraph;
+mod envs;
 mod fs;
+mod history;
+mod import;
+mod lock;
+mod lsp;
 mod path;
+mod progress;
+mod prompt;
+mod runner;
 mod rusk;
+mod sandbox;
+mod scheduler;
+mod service;
+mod show;
+mod summary;
 mod taskkey;
+mod trust;
+mod vfs;
 
 /// Abort the program with a message.
 #[cold]
 fn abort(title: &'static str, msg: impl Display, code: i32) -> ! {
-    eprintln!("{}: {}", title.bold().red(), msg);
+    eprintln!(
+        "{}",
+        color::stderr(format_args!("{}: {}", title.bold().red(), msg))
+    );
     std::process::exit(code);
 }
 
@@ -30,9 +54,14 @@ const SCAN_TIMEOUT: Duration = Duration::from_millis(500);
 
 #[tokio::main]
 async fn main() {
-    let args = Args::new();
+    if let Some(script) = std::env::var_os(sandbox::SANDBOX_SCRIPT_ENV) {
+        std::process::exit(sandbox::run_sandboxed(script).await);
+    }
+
+    let args = Args::new().unwrap_or_else(|err| abort("error", err, 1));
+    args.opts.color.apply();
 
-    let mut composer = RuskfileComposer::new();
+    let mut composer = RuskfileComposer::new().with_imports(args.opts.imports);
     // TODO: Config to select either Project root or Current dir as root
     if tokio::time::timeout(SCAN_TIMEOUT, composer.walkdir(get_current_dir()))
         .await
@@ -45,17 +74,169 @@ async fn main() {
         );
     }
 
+    if args.opts.lock {
+        let lockfile = composer.lockfile(get_current_dir());
+        if let Err(err) = lockfile.write(get_current_dir()).await {
+            abort("error", err, 1);
+        }
+        eprintln!("{} {}", color::stderr("Wrote".dimmed()), LOCK_FILE);
+        return;
+    }
+
+    if args.opts.trust {
+        match trust::trust_all(composer.ruskfiles()).await {
+            Ok(trusted) => {
+                for path in trusted {
+                    eprintln!("{} {}", color::stderr("Trusted".dimmed()), path.display());
+                }
+            }
+            Err(err) => abort("error", err, 1),
+        }
+        return;
+    }
+
+    if args.opts.locked {
+        let res = Lockfile::read(get_current_dir())
+            .await
+            .and_then(|pinned| pinned.verify(&composer.lockfile(get_current_dir())));
+        if let Err(err) = res {
+            abort("error", err, 1);
+        }
+    }
+
+    if args.opts.show {
+        let res: Result<_, MainError> = async move {
+            let opts = ExecuteOpts {
+                vars: args.opts.vars.iter().cloned().collect(),
+                ..Default::default()
+            };
+            let rusk = Rusk::compose(composer, &opts)?;
+            let mut views = Vec::new();
+            for arg in args {
+                views.push(rusk.show(arg, &opts)?);
+            }
+            Ok(views)
+        }
+        .await;
+        match res {
+            Ok(views) => {
+                let views = views.iter().map(ToString::to_string).join("\n");
+                print!("{}", color::stdout(views));
+            }
+            Err(err) => abort("error", err, 1),
+        }
+        return;
+    }
+
+    if args.opts.history {
+        let res: Result<_, MainError> = async move {
+            let rusk = Rusk::try_from(composer)?;
+            let mut stats = Vec::new();
+            for arg in args {
+                stats.push((
+                    arg.clone(),
+                    rusk.history(arg, &ExecuteOpts::default()).await?,
+                ));
+            }
+            Ok(stats)
+        }
+        .await;
+        match res {
+            Ok(stats) => {
+                for (name, stats) in stats {
+                    println!("{}\n{}\n", color::stdout(name.bold()), color::stdout(stats));
+                }
+            }
+            Err(err) => abort("error", err, 1),
+        }
+        return;
+    }
+
+    if args.opts.clean {
+        let dry_run = args.opts.dry_run;
+        let res: Result<_, MainError> = async move {
+            let rusk = Rusk::try_from(composer)?;
+            Ok(rusk.clean(args, dry_run, &ExecuteOpts::default()).await?)
+        }
+        .await;
+        match res {
+            Ok(removed) => {
+                let verb = if dry_run { "Would remove" } else { "Removed" };
+                for path in removed {
+                    eprintln!("{} {}", color::stderr(verb.dimmed()), color::stderr(path));
+                }
+            }
+            Err(err) => abort("error", err, 1),
+        }
+        return;
+    }
+
+    if args.opts.details {
+        let include_hidden = args.opts.all;
+        let dir = args.into_iter().next().map_or_else(
+            || get_current_dir().clone(),
+            |dir| NormarizedPath::from(Path::new(&dir)),
+        );
+        let mut stdout = BufWriter::new(std::io::stdout().lock());
+        for task in composer.tasks_list_detailed(&dir, include_hidden) {
+            writeln!(stdout, "{}", task).unwrap();
+        }
+        stdout.flush().unwrap();
+        return;
+    }
+
+    if args.opts.check {
+        let diagnostics = Analysis::new(&composer).diagnostics(&RealFs).await;
+        for diagnostic in &diagnostics {
+            println!("{diagnostic}");
+        }
+        if diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+        {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.opts.definition {
+        let analysis = Analysis::new(&composer);
+        for arg in args {
+            let Some((path, position)) = Position::parse_location(&arg) else {
+                let err = ArgsError::InvalidValue {
+                    option: "--definition".to_owned(),
+                    value: arg,
+                };
+                abort("error", err, 1);
+            };
+            for span in analysis.definition(&Path::new(path).into(), position) {
+                println!("{span}");
+            }
+        }
+        return;
+    }
+
+    if args.opts.complete {
+        let analysis = Analysis::new(&composer);
+        for arg in args {
+            for name in analysis.completions(&Path::new(&arg).into()) {
+                println!("{name}");
+            }
+        }
+        return;
+    }
+
     if args.no_pargs() {
         {
             let stdout = std::io::stdout();
             let is_tty = stdout.is_terminal();
             let mut stdout = BufWriter::new(stdout.lock());
             if is_tty {
-                for task in composer.tasks_list_pretty() {
-                    writeln!(stdout, "{}", task).unwrap();
+                for task in composer.tasks_list_pretty(args.opts.all) {
+                    writeln!(stdout, "{}", color::stdout(task)).unwrap();
                 }
             } else {
-                for task in composer.tasks_list() {
+                for task in composer.tasks_list(args.opts.all) {
                     writeln!(stdout, "{}", task).unwrap();
                 }
             }
@@ -68,25 +249,46 @@ async fn main() {
                 writeln!(
                     stderr,
                     "\n{}: Cannot load files below",
-                    "warning".on_yellow().black().bold()
+                    color::stderr("warning".on_yellow().black().bold())
                 )
                 .unwrap();
             }
             for err in composer.errors_list().sorted() {
-                writeln!(stderr, "\n  {}", err.into_verbose()).unwrap();
+                writeln!(stderr, "\n  {}", color::stderr(err.into_verbose())).unwrap();
             }
             stderr.flush().unwrap();
         }
         return;
     }
 
+    let opts = ExecuteOpts {
+        assume_yes: args.opts.yes,
+        max_jobs: args.opts.jobs,
+        record_history: args.opts.record,
+        progress: args.opts.progress,
+        vars: args.opts.vars.iter().cloned().collect(),
+        ..Default::default()
+    };
+    let summary = opts.summary.clone();
     let res: Result<(), MainError> = async move {
-        let composer = Rusk::try_from(composer)?;
-        composer.exec(args, Default::default()).await?;
+        let composer = Rusk::compose(composer, &opts)?;
+        if let Some(iterations) = args.opts.bench {
+            for arg in args {
+                let stats = composer
+                    .bench(arg.clone(), iterations, opts.clone())
+                    .await?;
+                println!("{}\n{}\n", color::stdout(arg.bold()), color::stdout(stats));
+            }
+            return Ok(());
+        }
+        composer.exec(args, opts).await?;
         Ok(())
     }
     .await;
 
+    if summary.has_warnings() {
+        eprintln!("\n{}", color::stderr(&summary));
+    }
     if let Err(err) = res {
         let (title, code) = match &err {
             MainError::RuskError(RuskError::TaskFailed(TaskError::Execution {
@@ -0,0 +1,229 @@
+//! Progress of the execution shown while the tasks run.
+
+use std::{
+    cell::RefCell,
+    io::{IsTerminal, Write},
+    pin::pin,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+use itertools::Itertools;
+
+use crate::{color, taskkey::TaskKey};
+
+/// Interval to redraw the status line
+const TICK: Duration = Duration::from_millis(100);
+
+/// Maximum number of running tasks named in the status line, to keep it in one line
+const MAX_NAMED: usize = 3;
+
+/// Frames of the spinner
+const SPINNER: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Renderer of the execution progress driven by the task events.
+/// - On a terminal, a status line `[N/M] running: (tasks) (elapsed)` is redrawn in place.
+/// - Otherwise, a plain log line is written when each task starts and finishes.
+#[derive(Clone)]
+pub struct Progress {
+    inner: Rc<RefCell<ProgressInner>>,
+}
+
+struct ProgressInner {
+    /// Whether to redraw the status line in place
+    live: bool,
+    /// Number of tasks to be processed
+    total: usize,
+    /// Number of processed tasks
+    done: usize,
+    /// Running tasks with their start time
+    running: Vec<(TaskKey, Instant)>,
+    /// Start time of the whole execution
+    started: Instant,
+    /// Number of redraws
+    frame: usize,
+    /// Number of active [Progress::suspend] guards
+    suspended: usize,
+    /// Lines logged while suspended, written on resuming
+    deferred: Vec<String>,
+}
+
+impl Progress {
+    /// Create a renderer writing to stderr
+    pub fn new() -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(ProgressInner {
+                live: std::io::stderr().is_terminal(),
+                total: 0,
+                done: 0,
+                running: Vec::new(),
+                started: Instant::now(),
+                frame: 0,
+                suspended: 0,
+                deferred: Vec::new(),
+            })),
+        }
+    }
+
+    /// Set the number of tasks to be processed
+    pub fn set_total(&self, total: usize) {
+        self.inner.borrow_mut().total = total;
+    }
+
+    /// The script of the task has started
+    pub fn start(&self, key: &TaskKey) {
+        let mut inner = self.inner.borrow_mut();
+        inner.running.push((key.clone(), Instant::now()));
+        if !inner.live {
+            let line = format!("{} {} started", inner.counter(), key);
+            inner.log(&line);
+        }
+    }
+
+    /// The task has been processed, whether executed, skipped as up to date, or failed
+    pub fn finish(&self, key: &TaskKey, success: bool) {
+        let mut inner = self.inner.borrow_mut();
+        inner.done += 1;
+        let started = inner
+            .running
+            .iter()
+            .position(|(running, _)| running == key)
+            .map(|i| inner.running.remove(i).1);
+        let status = match (started, success) {
+            (Some(started), true) => format!("done in {:.2?}", started.elapsed()).green(),
+            (Some(started), false) => format!("failed in {:.2?}", started.elapsed()).red(),
+            (None, true) => return, // Nothing to report for the tasks not executed
+            (None, false) => "failed".red(),
+        };
+        let line = format!("{} {} {}", inner.counter(), key, status);
+        inner.log(&line);
+    }
+
+    /// Stop writing to the terminal until the guard is dropped, e.g. while prompting
+    pub fn suspend(&self) -> Suspended {
+        let mut inner = self.inner.borrow_mut();
+        if inner.suspended == 0 && inner.live {
+            inner.clear();
+        }
+        inner.suspended += 1;
+        Suspended {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Run the future while redrawing the status line
+    pub async fn run_until<T>(&self, future: impl Future<Output = T>) -> T {
+        if !self.inner.borrow().live {
+            return future.await;
+        }
+        let mut future = pin!(future);
+        let mut interval = tokio::time::interval(TICK);
+        let res = loop {
+            tokio::select! {
+                res = &mut future => break res,
+                _ = interval.tick() => self.inner.borrow_mut().render(),
+            }
+        };
+        self.inner.borrow().clear();
+        res
+    }
+}
+
+/// Guard of [Progress::suspend]. The deferred lines are written on drop.
+pub struct Suspended {
+    inner: Rc<RefCell<ProgressInner>>,
+}
+
+impl Drop for Suspended {
+    fn drop(&mut self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.suspended -= 1;
+        if inner.suspended == 0 {
+            for line in std::mem::take(&mut inner.deferred) {
+                inner.log(&line);
+            }
+        }
+    }
+}
+
+impl ProgressInner {
+    /// `[N/M]` counter
+    fn counter(&self) -> String {
+        let width = self.total.to_string().len();
+        format!("[{:>width$}/{}]", self.done, self.total)
+            .dimmed()
+            .to_string()
+    }
+
+    /// Write the line above the status line
+    fn log(&mut self, line: &str) {
+        if self.suspended > 0 {
+            self.deferred.push(line.to_owned());
+            return;
+        }
+        let mut stderr = std::io::stderr().lock();
+        if self.live {
+            let _ = write!(stderr, "\r\x1b[2K");
+        }
+        let _ = writeln!(stderr, "{}", color::stderr(line));
+    }
+
+    /// Redraw the status line
+    fn render(&mut self) {
+        if self.suspended > 0 {
+            return;
+        }
+        self.frame += 1;
+        let spinner = SPINNER[self.frame % SPINNER.len()];
+        let mut running = self
+            .running
+            .iter()
+            .take(MAX_NAMED)
+            .map(|(key, _)| key)
+            .join(", ");
+        if self.running.len() > MAX_NAMED {
+            running += &format!(" +{} more", self.running.len() - MAX_NAMED);
+        }
+        let elapsed = format!("{:.1?}", self.started.elapsed()).dimmed();
+        let mut stderr = std::io::stderr().lock();
+        let status = format!("{spinner} {} running: {running} {elapsed}", self.counter());
+        let _ = write!(stderr, "\r\x1b[2K{}", color::stderr(status));
+        let _ = stderr.flush();
+    }
+
+    /// Erase the status line
+    fn clear(&self) {
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{path::get_current_dir, taskkey::TaskKeyRelative};
+
+    use super::*;
+
+    #[test]
+    fn suspend_defers_output() {
+        let progress = Progress::new();
+        let key = TaskKeyRelative::try_from("build".to_owned())
+            .unwrap()
+            .into_task_key(get_current_dir());
+        let outer = progress.suspend();
+        let inner = progress.suspend();
+        progress.start(&key);
+        progress.finish(&key, true);
+        progress.inner.borrow_mut().render();
+        drop(inner);
+        {
+            let state = progress.inner.borrow();
+            assert_eq!(state.frame, 0);
+            assert_eq!(state.deferred.len(), if state.live { 1 } else { 2 });
+        }
+        drop(outer);
+        assert!(progress.inner.borrow().deferred.is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+//! Interactive prompts on the controlling terminal.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    sync::Arc,
+};
+
+use colored::Colorize;
+use tokio::sync::Mutex;
+
+/// Prompter which asks the user on the controlling terminal.
+/// - Stdin/Stdout of the tasks are not used, so that piped IO is not disturbed.
+#[derive(Clone, Default)]
+pub struct Prompter {
+    /// Answer yes to all prompts without asking
+    assume_yes: bool,
+    /// Lock to prevent prompts of concurrent tasks from interleaving
+    lock: Arc<Mutex<()>>,
+}
+
+impl Prompter {
+    /// Create a new Prompter
+    pub fn new(assume_yes: bool) -> Self {
+        Self {
+            assume_yes,
+            lock: Default::default(),
+        }
+    }
+    /// Ask the user for confirmation. Returns true if the user accepted.
+    pub async fn confirm(&self, message: &str) -> io::Result<bool> {
+        if self.assume_yes {
+            return Ok(true);
+        }
+        let _guard = self.lock.lock().await;
+        let message = message.to_owned();
+        tokio::task::spawn_blocking(move || confirm_blocking(&message))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+/// Blocking implementation of [Prompter::confirm]
+fn confirm_blocking(message: &str) -> io::Result<bool> {
+    let (mut reader, mut writer) = open_tty()?;
+    write!(writer, "{} {} ", message.bold(), "[y/N]".dimmed())?;
+    writer.flush()?;
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        // EOF: treat as declined
+        writeln!(writer)?;
+        return Ok(false);
+    }
+    Ok(matches!(
+        line.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// Open the controlling terminal as (reader, writer)
+#[cfg(unix)]
+fn open_tty() -> io::Result<(BufReader<File>, File)> {
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    Ok((BufReader::new(tty.try_clone()?), tty))
+}
+
+/// Open the controlling terminal as (reader, writer)
+#[cfg(windows)]
+fn open_tty() -> io::Result<(BufReader<File>, File)> {
+    let input = OpenOptions::new().read(true).write(true).open("CONIN$")?;
+    let output = OpenOptions::new().write(true).open("CONOUT$")?;
+    Ok((BufReader::new(input), output))
+}
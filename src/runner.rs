@@ -0,0 +1,121 @@
+//! Backends executing the scripts.
+
+#[cfg(test)]
+use std::{cell::RefCell, rc::Rc};
+use std::{collections::HashMap, ffi::OsString, io, path::PathBuf};
+
+use deno_task_shell::{
+    KillSignal, ShellPipeReader, ShellPipeWriter, ShellState, parser::SequentialList,
+};
+use futures::{FutureExt, future::LocalBoxFuture};
+
+use crate::sandbox::SandboxConfig;
+
+/// Script to be executed by a [TaskRunner]
+pub struct RunRequest {
+    /// Script as written
+    pub script: String,
+    /// Script parsed by deno_task_shell
+    pub parsed: SequentialList,
+    /// Environment variables
+    pub envs: HashMap<OsString, OsString>,
+    /// Working directory
+    pub cwd: PathBuf,
+    /// Stdin
+    pub stdin: ShellPipeReader,
+    /// Stdout
+    pub stdout: ShellPipeWriter,
+    /// Stderr
+    pub stderr: ShellPipeWriter,
+    /// Signal to stop the execution
+    pub kill_signal: KillSignal,
+}
+
+/// Backend executing the scripts of tasks, services, env commands and health checks
+pub trait TaskRunner {
+    /// Execute the script and return the exit code
+    fn run(&self, request: RunRequest) -> LocalBoxFuture<'static, i32>;
+    /// Execute the script under the sandbox restrictions and return the exit code
+    fn run_sandboxed(
+        &self,
+        request: RunRequest,
+        config: SandboxConfig,
+    ) -> LocalBoxFuture<'static, io::Result<i32>>;
+}
+
+/// Default backend running the scripts with deno_task_shell
+pub struct ShellRunner;
+
+impl TaskRunner for ShellRunner {
+    fn run(&self, request: RunRequest) -> LocalBoxFuture<'static, i32> {
+        let RunRequest {
+            parsed,
+            envs,
+            cwd,
+            stdin,
+            stdout,
+            stderr,
+            kill_signal,
+            ..
+        } = request;
+        deno_task_shell::execute_with_pipes(
+            parsed,
+            ShellState::new(envs, cwd, Default::default(), kill_signal),
+            stdin,
+            stdout,
+            stderr,
+        )
+        .boxed_local()
+    }
+
+    fn run_sandboxed(
+        &self,
+        request: RunRequest,
+        config: SandboxConfig,
+    ) -> LocalBoxFuture<'static, io::Result<i32>> {
+        async move { config.execute(request).await }.boxed_local()
+    }
+}
+
+/// Runner recording the requests instead of executing them
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockRunner {
+    /// Scripts executed, in order. Stops of the long-running ones are recorded as `stop (script)`.
+    pub scripts: Rc<RefCell<Vec<String>>>,
+    /// Environment variables of each execution, in order
+    pub envs: RefCell<Vec<HashMap<OsString, OsString>>>,
+    /// Scripts exiting with 1
+    pub failing: Vec<&'static str>,
+    /// Scripts running until they are killed, like services
+    pub long_running: Vec<&'static str>,
+}
+
+#[cfg(test)]
+impl TaskRunner for MockRunner {
+    fn run(&self, request: RunRequest) -> LocalBoxFuture<'static, i32> {
+        let exit_code = self.failing.contains(&request.script.as_str()) as i32;
+        self.scripts.borrow_mut().push(request.script.clone());
+        self.envs.borrow_mut().push(request.envs);
+        if !self.long_running.contains(&request.script.as_str()) {
+            return futures::future::ready(exit_code).boxed_local();
+        }
+        let scripts = self.scripts.clone();
+        async move {
+            request.kill_signal.wait_aborted().await;
+            scripts
+                .borrow_mut()
+                .push(format!("stop {}", request.script));
+            exit_code
+        }
+        .boxed_local()
+    }
+
+    fn run_sandboxed(
+        &self,
+        request: RunRequest,
+        _config: SandboxConfig,
+    ) -> LocalBoxFuture<'static, io::Result<i32>> {
+        self.run(request).map(Ok).boxed_local()
+    }
+}
@@ -1,20 +1,38 @@
 use std::{
     cell::{Ref, RefCell},
-    ffi::OsString,
+    ffi::{OsStr, OsString},
     fmt::Debug,
     ops::Deref,
+    path::Path,
+    rc::Rc,
+    time::{Instant, SystemTime},
 };
 
-use deno_task_shell::{ShellPipeReader, ShellPipeWriter, ShellState, parser::SequentialList};
-use futures::future::try_join_all;
-use hashbrown::HashMap;
+use colored::Colorize;
+use deno_task_shell::{ShellPipeReader, ShellPipeWriter, parser::SequentialList};
+use futures::future::{join_all, try_join_all};
+use hashbrown::{HashMap, HashSet};
 use tokio::sync::watch::Receiver;
 
 use crate::{
+    bench::BenchStats,
+    color,
     digraph::{DigraphItem, TreeNode, TreeNodeCreationError},
-    fs::{RuskfileComposer, RuskfileDeserializeError},
+    envs::{EnvCommandCache, EnvSource, EnvValue},
+    fs::{RuskfileComposer, RuskfileDeserializeError, TaskLocation},
+    history::{DiskHistory, HistoryBackend, HistoryEntry, HistoryStore, TaskStats},
     path::{NormarizedPath, get_current_dir},
+    progress::Progress,
+    prompt::Prompter,
+    runner::{RunRequest, ShellRunner, TaskRunner},
+    sandbox::SandboxConfig,
+    scheduler::Scheduler,
+    service::{ServiceConfig, Services},
+    show::TaskView,
+    summary::RunSummary,
     taskkey::{TaskKey, TaskKeyParseError, TaskKeyRelative},
+    trust::{PromptTrust, TrustError, TrustPolicy},
+    vfs::{FsProvider, RealFs},
 };
 
 type TaskTree = TreeNode<TaskKey, TaskExecutable>;
@@ -34,6 +52,27 @@ pub enum RuskError {
     /// Task execution error
     #[error(transparent)]
     TaskFailed(#[from] TaskError),
+    /// Ruskfile is not trusted
+    #[error(transparent)]
+    Untrusted(#[from] TrustError),
+    /// Failed to read the history
+    #[error("Failed to read the history: {0}")]
+    HistoryUnavailable(std::io::Error),
+    /// Service tasks keep running, so they cannot be measured
+    #[error("Task {0} is a service and cannot be benchmarked")]
+    BenchService(TaskKey),
+    /// Output to be cleaned is outside the directory of its ruskfile
+    #[error("Refusing to remove {path} outside the directory of {ruskfile}")]
+    CleanOutside {
+        path: NormarizedPath,
+        ruskfile: NormarizedPath,
+    },
+    /// Failed to remove the output
+    #[error("Failed to remove {path}: {error}")]
+    RemoveFailed {
+        path: NormarizedPath,
+        error: std::io::Error,
+    },
 }
 
 /// IO set about deno_task_shell
@@ -70,6 +109,64 @@ impl TryFrom<RuskfileComposer> for Rusk {
 }
 
 impl Rusk {
+    /// Compose the tasks with the variables overridden by [ExecuteOpts::vars]
+    pub fn compose(
+        composer: RuskfileComposer,
+        opts: &ExecuteOpts,
+    ) -> Result<Self, RuskfileDeserializeError> {
+        Ok(Rusk {
+            tasks: composer.compose(&opts.vars)?,
+        })
+    }
+    /// Resolve the task configuration to show it
+    #[allow(clippy::result_large_err)]
+    pub fn show(&self, arg: String, opts: &ExecuteOpts) -> Result<TaskView, RuskError> {
+        let key = TaskKeyRelative::try_from(arg)?.into_task_key(get_current_dir());
+        let Some((key, task)) = self.tasks.get_key_value(&key) else {
+            return Err(TreeNodeCreationError::ItemNotFound(key).into());
+        };
+
+        let mut envs: std::collections::BTreeMap<_, _> = opts
+            .envs
+            .iter()
+            .map(|(name, value)| {
+                let value = EnvValue::Static(value.clone());
+                (name.clone(), (value, EnvSource::Global))
+            })
+            .collect();
+        for (layer, source) in [
+            (&task.file_envs, EnvSource::File),
+            (&task.envs, EnvSource::Task),
+        ] {
+            for (name, value) in layer {
+                envs.insert(name.clone(), (value.clone(), source));
+            }
+        }
+
+        Ok(TaskView {
+            key: key.clone(),
+            source: task.source.clone(),
+            cwd: task.cwd.clone(),
+            depends: task.depends.clone(),
+            envs: envs.into_iter().collect(),
+            script: task.script.clone(),
+            parsed: parse_script(task.script.as_deref()).map_err(|err| err.to_string()),
+        })
+    }
+
+    /// Read the execution statistics of the task from the history
+    pub async fn history(&self, arg: String, opts: &ExecuteOpts) -> Result<TaskStats, RuskError> {
+        let key = TaskKeyRelative::try_from(arg)?.into_task_key(get_current_dir());
+        let Some((key, task)) = self.tasks.get_key_value(&key) else {
+            return Err(TreeNodeCreationError::ItemNotFound(key).into());
+        };
+        let dir = task.source.path.clone().into_parent().unwrap();
+        HistoryStore::new(&dir, key, opts.history.clone())
+            .stats()
+            .await
+            .map_err(RuskError::HistoryUnavailable)
+    }
+
     /// Execute tasks
     pub async fn exec(
         self,
@@ -77,41 +174,349 @@ impl Rusk {
         opts: ExecuteOpts,
     ) -> Result<(), RuskError> {
         let Rusk { tasks } = self;
-        let tasks = into_executable(tasks, opts)?;
-        let tk = args
-            .into_iter()
-            .map({
-                fn f(s: String) -> Result<TaskKey, TaskKeyParseError> {
-                    let key = TaskKeyRelative::try_from(s)?;
-                    Ok(key.into_task_key(get_current_dir()))
+        let tk = parse_targets(args)?;
+        let roots = tk
+            .iter()
+            .filter_map(|key| tasks.get_key_value(key).map(|(key, _)| key))
+            .collect();
+        ensure_trusted(&tasks, roots, opts.trust.as_ref()).await?;
+        run(tasks, tk, opts).await
+    }
+
+    /// Execute the task `iterations` times and measure the durations of its script.
+    /// - The dependencies are executed once beforehand, and not measured.
+    ///   Services among them keep running until all the iterations finish.
+    /// - The task is executed every time even if it is up to date.
+    /// - The trust, the confirmation and the env commands are resolved once before the iterations.
+    pub async fn bench(
+        &self,
+        arg: String,
+        iterations: usize,
+        opts: ExecuteOpts,
+    ) -> Result<BenchStats, RuskError> {
+        let key = TaskKeyRelative::try_from(arg)?.into_task_key(get_current_dir());
+        let Some((key, task)) = self.tasks.get_key_value(&key) else {
+            return Err(TreeNodeCreationError::ItemNotFound(key).into());
+        };
+        if task.service.is_some() {
+            return Err(RuskError::BenchService(key.clone()));
+        }
+        ensure_trusted(&self.tasks, vec![key], opts.trust.as_ref()).await?;
+
+        let deps = task
+            .depends
+            .iter()
+            .filter(|dep| self.tasks.contains_key(*dep))
+            .cloned()
+            .collect();
+        let summary = opts.summary.clone();
+        let measured = summary.durations(key).len();
+        let services = Services::new([], opts.runner.clone());
+        let res = services
+            .run_until(async {
+                execute(self.tasks.clone(), deps, opts.clone(), &services).await?;
+                // The confirmation and the env commands are resolved once, and only the script is repeated
+                let mut task = task.clone();
+                task.depends.clear();
+                let opts = ExecuteOpts {
+                    force: true,
+                    ..opts.clone()
+                };
+                let tasks = HashMap::from([(key.clone(), task)]);
+                let executable = into_executable(tasks, opts, &services, None)
+                    .await?
+                    .remove(key)
+                    .unwrap();
+                executable.check()?;
+                let TaskExecutableState::Initialized(inner) = executable.0.into_inner() else {
+                    unreachable!()
+                };
+                let Some(prepared) = inner.prepare().await? else {
+                    unreachable!("forced tasks are never up to date")
+                };
+                for _ in 0..iterations {
+                    prepared.run().await?;
                 }
-                f
+                Ok::<_, RuskError>(())
             })
-            .collect::<Result<Vec<_>, _>>()?;
-        let graph = TreeNode::new_vec(tasks, tk)?;
-        exec_all(graph).await?;
-        Ok(())
+            .await;
+        services.shutdown().await;
+        res?;
+        let mut samples = summary.durations(key);
+        Ok(BenchStats::new(samples.split_off(measured)))
+    }
+
+    /// Remove the output files of the File tasks and the declared outputs under the targets.
+    /// - If no targets are given, all tasks are selected.
+    /// - Returns the removed paths, or the paths to be removed if `dry_run` is true.
+    pub async fn clean(
+        self,
+        args: impl IntoIterator<Item = String>,
+        dry_run: bool,
+        opts: &ExecuteOpts,
+    ) -> Result<Vec<NormarizedPath>, RuskError> {
+        let Rusk { tasks } = self;
+        let tk = parse_targets(args)?;
+
+        // Collect tasks under the targets
+        let roots = if tk.is_empty() {
+            tasks.keys().collect()
+        } else {
+            let mut roots = Vec::new();
+            for key in tk.iter() {
+                let Some((key, _)) = tasks.get_key_value(key) else {
+                    return Err(TreeNodeCreationError::ItemNotFound(key.clone()).into());
+                };
+                roots.push(key);
+            }
+            roots
+        };
+        if !dry_run {
+            ensure_trusted(&tasks, roots.clone(), opts.trust.as_ref()).await?;
+        }
+        let selected = collect_dependencies(&tasks, roots);
+
+        // Collect outputs of the selected tasks
+        let mut outputs = Vec::new();
+        for key in selected {
+            let task = &tasks[key];
+            let file = match key {
+                TaskKey::File(path) => Some(path),
+                TaskKey::Phony(_) => None,
+            };
+            for path in file.into_iter().chain(&task.outputs) {
+                ensure_removable(path, &task.source.path).await?;
+                outputs.push(path.clone());
+            }
+        }
+        outputs.sort_by(|a, b| a.as_abs_str().cmp(b.as_abs_str()));
+        outputs.dedup();
+
+        // Check all outputs before removing any of them
+        let mut existing = Vec::new();
+        for path in outputs {
+            let Ok(metadata) = tokio::fs::symlink_metadata(&path).await else {
+                continue; // Not exists
+            };
+            existing.push((path, metadata));
+        }
+
+        let mut removed = Vec::new();
+        for (path, metadata) in existing {
+            if !dry_run {
+                let res = if metadata.is_dir() {
+                    tokio::fs::remove_dir_all(&path).await
+                } else {
+                    tokio::fs::remove_file(&path).await
+                };
+                if let Err(error) = res {
+                    return Err(RuskError::RemoveFailed { path, error });
+                }
+            }
+            removed.push(path);
+        }
+        Ok(removed)
+    }
+}
+
+/// Ensure that `clean` may remove the output of the task defined in the ruskfile.
+/// - The output must be strictly below the directory of the ruskfile, also after resolving symlinks.
+/// - The output must not be the current directory or its ancestor.
+async fn ensure_removable(
+    path: &NormarizedPath,
+    ruskfile: &NormarizedPath,
+) -> Result<(), RuskError> {
+    let outside = || RuskError::CleanOutside {
+        path: path.clone(),
+        ruskfile: ruskfile.clone(),
+    };
+    let Some(root) = ruskfile.parent() else {
+        return Err(outside());
+    };
+    let inside = |path: &Path, root: &Path| path != root && path.starts_with(root);
+    if !inside(path, root) || get_current_dir().starts_with(path) {
+        return Err(outside());
+    }
+    // NOTE: Symlinked directories between the root and the output may lead outside
+    if let (Some(parent), Some(name)) = (path.parent(), path.file_name())
+        && let (Ok(parent), Ok(root)) = (
+            tokio::fs::canonicalize(parent).await,
+            tokio::fs::canonicalize(root).await,
+        )
+        && !inside(&parent.join(name), &root)
+    {
+        return Err(outside());
+    }
+    Ok(())
+}
+
+/// Ensure that the ruskfiles defining the roots and their dependencies are trusted
+async fn ensure_trusted(
+    tasks: &HashMap<TaskKey, Task>,
+    roots: Vec<&TaskKey>,
+    trust: &dyn TrustPolicy,
+) -> Result<(), TrustError> {
+    let sources: HashSet<&Path> = collect_dependencies(tasks, roots)
+        .into_iter()
+        .map(|key| tasks[key].source.path.deref())
+        .collect();
+    trust.ensure_trusted(sources.into_iter().collect()).await
+}
+
+/// Collect the tasks reachable from the roots through the dependencies
+fn collect_dependencies<'a>(
+    tasks: &'a HashMap<TaskKey, Task>,
+    mut stack: Vec<&'a TaskKey>,
+) -> HashSet<&'a TaskKey> {
+    let mut selected = HashSet::new();
+    while let Some(key) = stack.pop() {
+        if !selected.insert(key) {
+            continue;
+        }
+        // NOTE: File dependencies which are not tasks are source files, so they are not selected.
+        stack.extend(
+            tasks[key]
+                .depends
+                .iter()
+                .filter_map(|dep| tasks.get_key_value(dep).map(|(dep, _)| dep)),
+        );
+    }
+    selected
+}
+
+/// Execute the targets and their dependencies, then stop the services
+async fn run(
+    tasks: HashMap<TaskKey, Task>,
+    tk: Vec<TaskKey>,
+    opts: ExecuteOpts,
+) -> Result<(), RuskError> {
+    let services = Services::new(tk.iter().cloned(), opts.runner.clone());
+    let res = services
+        .run_until(execute(tasks, tk, opts, &services))
+        .await;
+    services.shutdown().await;
+    res
+}
+
+/// Execute the targets and their dependencies, launching the services into `services`.
+/// - Must be driven by [Services::run_until]; the services keep running after this returns.
+async fn execute(
+    mut tasks: HashMap<TaskKey, Task>,
+    tk: Vec<TaskKey>,
+    opts: ExecuteOpts,
+    services: &Services,
+) -> Result<(), RuskError> {
+    if opts.max_jobs.is_some() {
+        weigh_by_history(&mut tasks, &opts.history).await;
+    }
+    let summary = opts.summary.clone();
+    let progress = opts.progress.then(Progress::new);
+    let tasks = into_executable(tasks, opts, services, progress.as_ref()).await?;
+    let graph = TreeNode::new_vec(tasks, tk)?;
+    check_all(&graph)?;
+    prioritize(&graph);
+    match &progress {
+        Some(progress) => {
+            progress.set_total(count_pending(&graph));
+            progress.run_until(exec_all(graph)).await?;
+        }
+        None => exec_all(graph).await?,
+    }
+    services.wait_foreground(&summary).await?;
+    Ok(())
+}
+
+/// Add the average duration in seconds recorded in the history to the priority of each task.
+/// - Long tasks are scheduled first as they are likely on the critical path.
+async fn weigh_by_history(tasks: &mut HashMap<TaskKey, Task>, backend: &Rc<dyn HistoryBackend>) {
+    let weights = join_all(tasks.iter().map(|(key, task)| async move {
+        let dir = task.source.path.clone().into_parent().unwrap();
+        let stats = HistoryStore::new(&dir, key, backend.clone())
+            .stats()
+            .await
+            .ok()?;
+        Some((key.clone(), stats.average_duration?.as_secs() as i64))
+    }))
+    .await;
+    for (key, weight) in weights.into_iter().flatten() {
+        let task = tasks.get_mut(&key).unwrap();
+        task.priority = task.priority.saturating_add(weight);
     }
 }
 
+/// Parse the arguments as TaskKeys relative to the current directory
+fn parse_targets(
+    args: impl IntoIterator<Item = String>,
+) -> Result<Vec<TaskKey>, TaskKeyParseError> {
+    args.into_iter()
+        .map(|s| Ok(TaskKeyRelative::try_from(s)?.into_task_key(get_current_dir())))
+        .collect()
+}
+
 /// Task configuration
+#[derive(Clone)]
 pub struct Task {
     /// Environment variables that are specific to this task
-    pub envs: HashMap<OsString, OsString>,
+    pub envs: HashMap<OsString, EnvValue>,
+    /// Environment variables shared in the ruskfile
+    pub file_envs: HashMap<OsString, EnvValue>,
+    /// Location where this task is defined
+    pub source: TaskLocation,
     /// Script to be executed
     pub script: Option<String>,
     /// Working directory
     pub cwd: NormarizedPath,
     /// Dependencies
     pub depends: Vec<TaskKey>,
+    /// Deprecation message
+    pub deprecated: Option<String>,
+    /// Confirmation message
+    pub confirm: Option<String>,
+    /// Environment variables which must be set
+    pub required_envs: Vec<String>,
+    /// Service configuration if the task is a long-running service
+    pub service: Option<ServiceConfig>,
+    /// Files generated by this task other than the task itself
+    pub outputs: Vec<NormarizedPath>,
+    /// Scheduling priority under the jobs limit
+    pub priority: i64,
+    /// Restrictions on the script execution
+    pub sandbox: Option<SandboxConfig>,
+    /// Whether the run goes on even if the script fails
+    pub allow_failure: bool,
+    /// Exit codes regarded as success
+    pub success_codes: Vec<i32>,
 }
 
 /// Task execution global options
+#[derive(Clone)]
 pub struct ExecuteOpts {
     /// Environment variables
     pub envs: HashMap<OsString, OsString>,
     /// IO
     pub io: IOSet,
+    /// Answer yes to all confirmations
+    pub assume_yes: bool,
+    /// Maximum number of scripts running at once. `None` means unlimited.
+    pub max_jobs: Option<usize>,
+    /// Record the executions to the history
+    pub record_history: bool,
+    /// Storage of the history
+    pub history: Rc<dyn HistoryBackend>,
+    /// Execute File tasks even if they are up to date
+    pub force: bool,
+    /// Backend to run the scripts
+    pub runner: Rc<dyn TaskRunner>,
+    /// Filesystem to check the freshness of File tasks
+    pub fs: Rc<dyn FsProvider>,
+    /// Policy to check the trust of the ruskfiles before executing
+    pub trust: Rc<dyn TrustPolicy>,
+    /// Show the progress of the execution on stderr
+    pub progress: bool,
+    /// Summary to record the outcomes of the scripts. Keep a clone to read it after the run.
+    pub summary: RunSummary,
+    /// Variables overriding the `[vars]` of the ruskfiles. Applied by [Rusk::compose].
+    pub vars: HashMap<String, String>,
 }
 
 impl Default for ExecuteOpts {
@@ -119,41 +524,76 @@ impl Default for ExecuteOpts {
         Self {
             envs: std::env::vars_os().collect(),
             io: Default::default(),
+            assume_yes: false,
+            max_jobs: None,
+            record_history: false,
+            history: Rc::new(DiskHistory),
+            force: false,
+            runner: Rc::new(ShellRunner),
+            fs: Rc::new(RealFs),
+            trust: Rc::new(PromptTrust),
+            progress: false,
+            summary: Default::default(),
+            vars: HashMap::new(),
         }
     }
 }
 
 /// Alternative for `TryInto<HashMap<_, TaskExecutable>>` for `HashMap<_, Task>`
-fn into_executable(
+async fn into_executable(
     tasks: HashMap<TaskKey, Task>,
     ExecuteOpts {
         envs: global_env,
         io,
+        assume_yes,
+        max_jobs,
+        record_history,
+        history: history_backend,
+        force,
+        runner,
+        fs,
+        summary,
+        ..
     }: ExecuteOpts,
+    services: &Services,
+    progress: Option<&Progress>,
 ) -> Result<HashMap<TaskKey, TaskExecutable>, TaskParseError> {
     let mut parsed_tasks: HashMap<TaskKey, TaskExecutable> = HashMap::new();
+    let prompter = Prompter::new(assume_yes);
+    let scheduler = Scheduler::new(max_jobs);
+    let env_cache = EnvCommandCache::new(io.clone(), runner.clone());
 
     for (key, task) in tasks {
-        let script = {
-            let mut items = Vec::new();
-            if let Some(script) = task.script {
-                for line in script.lines() {
-                    items.extend(match deno_task_shell::parser::parse(line) {
-                        Ok(script) => script.items,
-                        Err(error) => {
-                            return Err(TaskParseError::ScriptParseError { key, error })?;
-                        }
-                    });
-                }
-            };
-            SequentialList { items }
+        let raw_script = task.script.clone().unwrap_or_default();
+        let script = match parse_script(task.script.as_deref()) {
+            Ok(script) => script,
+            Err(error) => {
+                return Err(TaskParseError::ScriptParseError { key, error })?;
+            }
         };
 
         let Task {
-            envs, cwd, depends, ..
+            envs,
+            file_envs,
+            source,
+            cwd,
+            depends,
+            deprecated,
+            confirm,
+            required_envs,
+            service,
+            priority,
+            sandbox,
+            allow_failure,
+            success_codes,
+            ..
         } = task;
 
-        if !cwd.is_dir() {
+        if sandbox.is_some() && service.is_some() {
+            return Err(TaskParseError::SandboxedService(key));
+        }
+
+        if !fs.is_dir(&cwd).await {
             return Err(TaskParseError::DirectoryNotFound(cwd));
         }
 
@@ -167,6 +607,37 @@ fn into_executable(
             }
         }
 
+        let history = record_history.then(|| {
+            HistoryStore::new(
+                &source.path.clone().into_parent().unwrap(),
+                &key,
+                history_backend.clone(),
+            )
+        });
+
+        let mut merged_envs: std::collections::HashMap<_, _> =
+            global_env.clone().into_iter().collect();
+        let mut env_commands = Vec::new();
+        let mut layered_envs = file_envs;
+        layered_envs.extend(envs);
+        for (name, value) in layered_envs {
+            match value {
+                EnvValue::Static(value) => {
+                    merged_envs.insert(name, value);
+                }
+                EnvValue::Command(cmd) => env_commands.push((name, cmd)),
+            }
+        }
+        // NOTE: Env commands run before the script and outside of the sandbox
+        if sandbox.is_some()
+            && let Some((name, _)) = env_commands.first()
+        {
+            return Err(TaskParseError::SandboxedEnvCommand {
+                key,
+                name: name.to_string_lossy().into_owned(),
+            });
+        }
+
         parsed_tasks.insert(
             key.clone(),
             TaskExecutableInner {
@@ -174,8 +645,27 @@ fn into_executable(
                 key,
                 script,
                 depends,
-                envs: global_env.clone().into_iter().chain(envs).collect(),
+                envs: merged_envs,
+                env_commands,
+                env_cache: env_cache.clone(),
                 cwd,
+                deprecated,
+                confirm: confirm.map(|message| (message, prompter.clone())),
+                required_envs,
+                service: service.map(|config| (config, services.clone())),
+                priority,
+                effective_priority: None,
+                scheduler: scheduler.clone(),
+                history,
+                raw_script,
+                sandbox,
+                runner: runner.clone(),
+                fs: fs.clone(),
+                progress: progress.cloned(),
+                force,
+                allow_failure,
+                success_codes,
+                summary: summary.clone(),
             }
             .into(),
         );
@@ -184,6 +674,82 @@ fn into_executable(
     Ok(parsed_tasks)
 }
 
+/// Parse the script line by line
+pub fn parse_script(script: Option<&str>) -> Result<SequentialList, anyhow::Error> {
+    let mut items = Vec::new();
+    for line in script.unwrap_or_default().lines() {
+        items.extend(deno_task_shell::parser::parse(line)?.items);
+    }
+    Ok(SequentialList { items })
+}
+
+/// Check that all tasks in the trees can be executed before running any of them
+#[allow(clippy::result_large_err)]
+fn check_all(roots: &[TaskTree]) -> TaskResult {
+    fn check_node(node: &TaskTree, visited: &mut HashSet<*const TaskTree>) -> TaskResult {
+        if !visited.insert(node) {
+            return Ok(());
+        }
+        for child in node.children.iter() {
+            check_node(child, visited)?;
+        }
+        node.item.check()
+    }
+
+    let mut visited = HashSet::new();
+    for root in roots {
+        check_node(root, &mut visited)?;
+    }
+    Ok(())
+}
+
+/// Count the tasks in the trees to be executed
+fn count_pending(roots: &[TaskTree]) -> usize {
+    fn count_node(node: &TaskTree, visited: &mut HashSet<*const TaskTree>) -> usize {
+        if !visited.insert(node) {
+            return 0;
+        }
+        let own = matches!(&*node.item.0.borrow(), TaskExecutableState::Initialized(_)) as usize;
+        own + node
+            .children
+            .iter()
+            .map(|child| count_node(child, visited))
+            .sum::<usize>()
+    }
+
+    let mut visited = HashSet::new();
+    roots
+        .iter()
+        .map(|root| count_node(root, &mut visited))
+        .sum()
+}
+
+/// Propagate priorities to dependencies so that the critical path is scheduled first.
+/// - The priority of a task becomes its own plus the highest one among its dependents.
+fn prioritize(roots: &[TaskTree]) {
+    fn prioritize_node(node: &TaskTree, inherited: i64) {
+        let priority = {
+            let mut state = node.item.0.borrow_mut();
+            let TaskExecutableState::Initialized(inner) = &mut *state else {
+                return;
+            };
+            let priority = inner.priority.saturating_add(inherited);
+            if inner.effective_priority.is_some_and(|p| p >= priority) {
+                return; // Already propagated from a more important dependent
+            }
+            inner.effective_priority = Some(priority);
+            priority
+        };
+        for child in node.children.iter() {
+            prioritize_node(child, priority);
+        }
+    }
+
+    for root in roots {
+        prioritize_node(root, 0);
+    }
+}
+
 async fn exec_all(roots: impl IntoIterator<Item = TaskTree>) -> TaskResult {
     async fn exec_node(node: &TaskTree) -> TaskResult {
         let child_futures = node.children.iter().map(|child| exec_node(child));
@@ -206,6 +772,34 @@ impl TaskExecutable {
     fn empty() -> Self {
         TaskExecutable(RefCell::new(TaskExecutableState::Done(Ok(()))))
     }
+    /// Check the requirements of the task before execution
+    #[allow(clippy::result_large_err)]
+    fn check(&self) -> TaskResult {
+        let TaskExecutableState::Initialized(inner) = &*self.0.borrow() else {
+            return Ok(());
+        };
+        let missing: Vec<_> = inner
+            .required_envs
+            .iter()
+            .filter(|name| {
+                let name = OsStr::new(name);
+                !inner.envs.contains_key(name)
+                    && !inner
+                        .env_commands
+                        .iter()
+                        .any(|(cmd_name, _)| cmd_name == name)
+            })
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(TaskError::MissingEnv {
+                key: inner.key.clone(),
+                names: missing,
+            })
+        }
+    }
     pub async fn as_future(&self) -> TaskResult {
         let res = 'res: {
             'early_return: {
@@ -235,7 +829,12 @@ impl TaskExecutable {
             ) else {
                 unreachable!()
             };
+            let key = inner.key.clone();
+            let progress = inner.progress.clone();
             let res = inner.into_future().await;
+            if let Some(progress) = progress {
+                progress.finish(&key, res.is_ok());
+            }
             tx.send(Some(res.clone())).unwrap();
             res
         };
@@ -247,49 +846,93 @@ impl TaskExecutable {
 
 impl TaskExecutableInner {
     pub async fn into_future(self) -> TaskResult {
+        match self.prepare().await? {
+            Some(prepared) => prepared.run().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Check the freshness, ask the confirmation and evaluate the env commands before running the script.
+    /// - Returns `None` if the File task is up to date.
+    async fn prepare(self) -> Result<Option<PreparedTask>, TaskError> {
         let TaskExecutableInner {
-            io,
+            mut io,
             key,
-            envs,
+            mut envs,
+            env_commands,
+            env_cache,
             script,
             cwd,
             depends,
+            deprecated,
+            confirm,
+            service,
+            priority,
+            effective_priority,
+            scheduler,
+            history,
+            raw_script,
+            sandbox,
+            runner,
+            fs,
+            progress,
+            force,
+            allow_failure,
+            success_codes,
+            summary,
+            ..
         } = self;
 
+        if let Some(message) = deprecated {
+            let _ = io.stderr.write_line(
+                &color::stderr(format_args!(
+                    "{}: Task {key} is deprecated: {message}",
+                    "warning".yellow().bold()
+                ))
+                .to_string(),
+            );
+        }
+
         'check_file: {
+            if force {
+                break 'check_file;
+            }
             match &key {
                 TaskKey::File(file) => {
-                    // Step 1: Collect dependency file Metadata Objects.
+                    // Step 1: Collect modified datetimes of dependency files.
                     // If File not found, the task won't be executed. So check at this point
-                    let mut dep_file_metadatas = Vec::new();
+                    // For directories, the newest one among the entries below is used.
+                    let mut dep_file_modifieds = Vec::new();
                     let dep_count = depends.len();
                     for dep in depends {
                         if let TaskKey::File(dep_file) = dep {
-                            let Ok(metadata) = tokio::fs::metadata(&dep_file).await else {
+                            if !fs.exists(&dep_file).await {
                                 return Err(TaskError::DependencyFileNotFound {
                                     dep_file,
                                     task: key,
                                 });
+                            }
+                            let Ok(modified) = fs.modified(&dep_file).await else {
+                                return Err(TaskError::FailedToGetFileMetadata);
                             };
-                            dep_file_metadatas.push(metadata);
+                            dep_file_modifieds.push(modified);
                         }
                     }
-                    if dep_count != dep_file_metadatas.len() {
+                    if dep_count != dep_file_modifieds.len() {
                         // NOTE: If PhonyTask is included, the script is always executed.
                         break 'check_file;
                     }
 
-                    // Step 2: Get the metadata of the file.
+                    // Step 2: Get the modified datetime of the file.
                     // If file not found, it need not to check the modified datetime
-                    let Ok(metadata) = tokio::fs::metadata(file).await else {
+                    if !fs.exists(file).await {
                         break 'check_file;
-                    };
-                    let Ok(modified) = metadata.modified() else {
+                    }
+                    let Ok(modified) = fs.modified(file).await else {
                         return Err(TaskError::FailedToGetFileMetadata);
                     };
 
-                    for dep in dep_file_metadatas {
-                        let dep_modified = dep.modified().unwrap(); // Checked above
+                    for dep_modified in dep_file_modifieds {
                         if modified <= dep_modified {
                             // Execution is required if the dependency file has been updated
                             break 'check_file;
@@ -297,13 +940,13 @@ impl TaskExecutableInner {
                     }
 
                     // If none have been updated
-                    return Ok(());
+                    return Ok(None);
                 }
                 TaskKey::Phony(_) => {
                     // Check only the existence of the dependency file
                     for dep in depends {
                         if let TaskKey::File(file) = dep
-                            && !matches!(tokio::fs::try_exists(&file).await, Ok(true))
+                            && !fs.exists(&file).await
                         {
                             return Err(TaskError::DependencyFileNotFound {
                                 dep_file: file,
@@ -314,23 +957,176 @@ impl TaskExecutableInner {
                 }
             }
         }
-        let exit_code = deno_task_shell::execute_with_pipes(
+        if let Some((message, prompter)) = confirm {
+            // The status line would erase the prompt
+            let _suspended = progress.as_ref().map(Progress::suspend);
+            match prompter.confirm(&message).await {
+                Ok(true) => {}
+                Ok(false) => return Err(TaskError::Declined { key }),
+                Err(err) => {
+                    return Err(TaskError::ConfirmationUnavailable {
+                        key,
+                        message: err.to_string(),
+                    });
+                }
+            }
+        }
+        // The commands see the static envs of the task, but not the results of each other
+        let static_envs = envs.clone();
+        for (name, cmd) in env_commands {
+            match env_cache.evaluate(&cmd, &cwd, &static_envs).await {
+                Ok(value) => {
+                    envs.insert(name, value);
+                }
+                Err(message) => {
+                    return Err(TaskError::EnvCommand {
+                        key,
+                        name: name.to_string_lossy().into_owned(),
+                        message,
+                    });
+                }
+            }
+        }
+        Ok(Some(PreparedTask {
+            io,
+            key,
+            envs,
             script,
-            ShellState::new(
-                envs,
-                cwd.to_path_buf(),
-                Default::default(),
-                Default::default(),
-            ),
-            io.stdin,
-            io.stdout,
-            io.stderr,
-        )
-        .await;
-        if exit_code == 0 {
+            cwd,
+            service,
+            priority: effective_priority.unwrap_or(priority),
+            scheduler,
+            history,
+            raw_script,
+            sandbox,
+            runner,
+            progress,
+            allow_failure,
+            success_codes,
+            summary,
+        }))
+    }
+}
+
+/// Task whose script is ready to run. It can be run repeatedly, e.g. in benchmarks.
+struct PreparedTask {
+    /// IO set
+    io: IOSet,
+    /// TaskKey
+    key: TaskKey,
+    /// Environment variables including those computed from the commands
+    envs: std::collections::HashMap<OsString, OsString>,
+    /// Script to be executed
+    script: SequentialList,
+    /// Working directory
+    cwd: NormarizedPath,
+    /// Service configuration and the services to register
+    service: Option<(ServiceConfig, Services)>,
+    /// Scheduling priority including those of the dependents
+    priority: i64,
+    /// Scheduler of the job slots
+    scheduler: Scheduler,
+    /// History to record the execution
+    history: Option<HistoryStore>,
+    /// Script as written
+    raw_script: String,
+    /// Sandbox restrictions
+    sandbox: Option<SandboxConfig>,
+    /// Backend to run the script
+    runner: Rc<dyn TaskRunner>,
+    /// Renderer of the progress
+    progress: Option<Progress>,
+    /// Whether the run goes on even if the script fails
+    allow_failure: bool,
+    /// Exit codes regarded as success
+    success_codes: Vec<i32>,
+    /// Summary to record the outcome
+    summary: RunSummary,
+}
+
+impl PreparedTask {
+    /// Run the script, or launch it if the task is a service, and record the outcome
+    async fn run(&self) -> TaskResult {
+        let PreparedTask {
+            io,
+            key,
+            envs,
+            script,
+            cwd,
+            service,
+            priority,
+            scheduler,
+            history,
+            raw_script,
+            sandbox,
+            runner,
+            progress,
+            allow_failure,
+            success_codes,
+            summary,
+        } = self;
+        let request = RunRequest {
+            script: raw_script.clone(),
+            parsed: script.clone(),
+            envs: envs.clone(),
+            cwd: cwd.to_path_buf(),
+            stdin: io.stdin.clone(),
+            stdout: io.stdout.clone(),
+            stderr: io.stderr.clone(),
+            kill_signal: Default::default(),
+        };
+        if let Some((config, services)) = service {
+            if let Some(progress) = progress {
+                progress.start(key);
+            }
+            return services.launch(key.clone(), config.clone(), request).await;
+        }
+        let _slot = scheduler.acquire(*priority).await;
+        if let Some(progress) = progress {
+            progress.start(key);
+        }
+        let timestamp = SystemTime::now();
+        let started = Instant::now();
+        let exit_code = match sandbox {
+            Some(config) => match runner.run_sandboxed(request, config.clone()).await {
+                Ok(exit_code) => exit_code,
+                Err(err) => {
+                    return Err(TaskError::Sandbox {
+                        key: key.clone(),
+                        message: err.to_string(),
+                    });
+                }
+            },
+            None => runner.run(request).await,
+        };
+        let duration = started.elapsed();
+        if let Some(history) = history {
+            let entry = HistoryEntry {
+                timestamp,
+                duration,
+                exit_code,
+            };
+            if let Err(err) = history.append(&entry).await {
+                let _ = io.stderr.clone().write_line(
+                    &color::stderr(format_args!(
+                        "{}: Failed to record the history of Task {key}: {err}",
+                        "warning".yellow().bold()
+                    ))
+                    .to_string(),
+                );
+            }
+        }
+        summary.record_exit(key, duration);
+        if success_codes.contains(&exit_code) {
+            Ok(())
+        } else if *allow_failure {
+            summary.record_allowed_failure(key, exit_code);
             Ok(())
         } else {
-            Err(TaskError::Execution { key, exit_code })
+            Err(TaskError::Execution {
+                key: key.clone(),
+                exit_code,
+            })
         }
     }
 }
@@ -338,7 +1134,7 @@ impl TaskExecutableInner {
 /// TaskExecutable state
 enum TaskExecutableState {
     /// Task is not executed yet
-    Initialized(TaskExecutableInner),
+    Initialized(Box<TaskExecutableInner>),
     /// Task is being executed
     Processing(Receiver<Option<TaskResult>>),
     /// Task is done
@@ -353,17 +1149,57 @@ struct TaskExecutableInner {
     key: TaskKey,
     /// Environment variables
     envs: std::collections::HashMap<OsString, OsString>,
+    /// Environment variables computed from the commands before execution
+    env_commands: Vec<(OsString, String)>,
+    /// Cache of the env commands shared among tasks
+    env_cache: EnvCommandCache,
     /// Script to be executed
     script: SequentialList,
     /// Working directory
     cwd: NormarizedPath,
     /// TaskKeys that this task depends on
     depends: Vec<TaskKey>, // 依存関係の検索についてはTaskKeyを用いるか検討が必要
+    /// Deprecation message
+    deprecated: Option<String>,
+    /// Confirmation message and the prompter to ask
+    confirm: Option<(String, Prompter)>,
+    /// Environment variables which must be set
+    required_envs: Vec<String>,
+    /// Service configuration and the services to register
+    service: Option<(ServiceConfig, Services)>,
+    /// Scheduling priority of the task itself
+    priority: i64,
+    /// Scheduling priority including those of the dependents
+    effective_priority: Option<i64>,
+    /// Scheduler of the job slots
+    scheduler: Scheduler,
+    /// History to record the execution
+    history: Option<HistoryStore>,
+    /// Script as written
+    raw_script: String,
+    /// Sandbox restrictions
+    sandbox: Option<SandboxConfig>,
+    /// Backend to run the script
+    runner: Rc<dyn TaskRunner>,
+    /// Filesystem to check the freshness
+    fs: Rc<dyn FsProvider>,
+    /// Renderer of the progress
+    progress: Option<Progress>,
+    /// Whether to execute even if up to date
+    force: bool,
+    /// Whether the run goes on even if the script fails
+    allow_failure: bool,
+    /// Exit codes regarded as success
+    success_codes: Vec<i32>,
+    /// Summary to record the outcome
+    summary: RunSummary,
 }
 
 impl From<TaskExecutableInner> for TaskExecutable {
     fn from(val: TaskExecutableInner) -> Self {
-        TaskExecutable(RefCell::new(TaskExecutableState::Initialized(val)))
+        TaskExecutable(RefCell::new(TaskExecutableState::Initialized(Box::new(
+            val,
+        ))))
     }
 }
 
@@ -386,6 +1222,12 @@ pub enum TaskParseError {
     /// Task script parse error
     #[error("Task {key:?} script parse error: {error:?}")]
     ScriptParseError { key: TaskKey, error: anyhow::Error },
+    /// Sandbox is specified for a service
+    #[error("Task {0:?} cannot be sandboxed because it is a service")]
+    SandboxedService(TaskKey),
+    /// Env command is specified for a sandboxed task
+    #[error("Env {name} of Task {key:?} cannot be a command because the task is sandboxed")]
+    SandboxedEnvCommand { key: TaskKey, name: String },
 }
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -399,7 +1241,477 @@ pub enum TaskError {
         dep_file: NormarizedPath,
         task: TaskKey,
     },
+    #[error("Task {key:?} was declined")]
+    Declined { key: TaskKey },
+    #[error("Failed to confirm Task {key:?} on the terminal: {message}")]
+    ConfirmationUnavailable { key: TaskKey, message: String },
+    #[error("Task {key:?} could not start in the sandbox: {message}")]
+    Sandbox { key: TaskKey, message: String },
+    #[error("Task {key:?} requires environment variables: {}", names.join(", "))]
+    MissingEnv { key: TaskKey, names: Vec<String> },
+    #[error("Service {key:?} exited with code {exit_code} before it became ready")]
+    ServiceExited { key: TaskKey, exit_code: i32 },
+    #[error("Service {key:?} did not pass the ready_check in time")]
+    ServiceUnhealthy { key: TaskKey },
+    #[error("Failed to evaluate env {name} of Task {key:?}: {message}")]
+    EnvCommand {
+        key: TaskKey,
+        name: String,
+        message: String,
+    },
 }
 
 /// Task result alias
-type TaskResult = Result<(), TaskError>;
+pub type TaskResult = Result<(), TaskError>;
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use futures::{
+        FutureExt,
+        future::{LocalBoxFuture, ready},
+    };
+
+    use super::*;
+    use crate::{runner::MockRunner, vfs::MemoryFs};
+
+    /// Policy trusting all ruskfiles
+    struct TrustAll;
+
+    impl TrustPolicy for TrustAll {
+        fn ensure_trusted<'a>(
+            &'a self,
+            _files: Vec<&'a Path>,
+        ) -> LocalBoxFuture<'a, Result<(), TrustError>> {
+            ready(Ok(())).boxed_local()
+        }
+    }
+
+    /// History files on memory
+    #[derive(Default)]
+    struct MemoryHistory {
+        files: RefCell<HashMap<PathBuf, String>>,
+    }
+
+    impl HistoryBackend for MemoryHistory {
+        fn read<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, std::io::Result<String>> {
+            let content = self.files.borrow().get(path).cloned().unwrap_or_default();
+            ready(Ok(content)).boxed_local()
+        }
+
+        fn append<'a>(
+            &'a self,
+            path: &'a Path,
+            line: &'a str,
+        ) -> LocalBoxFuture<'a, std::io::Result<()>> {
+            self.files
+                .borrow_mut()
+                .entry(path.to_path_buf())
+                .or_default()
+                .push_str(line);
+            ready(Ok(())).boxed_local()
+        }
+    }
+
+    fn key(name: &str) -> TaskKey {
+        TaskKeyRelative::try_from(name.to_owned())
+            .unwrap()
+            .into_task_key(get_current_dir())
+    }
+
+    /// Task defined in `rusk.toml` of the current directory
+    fn task(script: &str, depends: &[&str]) -> Task {
+        Task {
+            envs: HashMap::new(),
+            file_envs: HashMap::new(),
+            source: TaskLocation {
+                path: get_current_dir().join("rusk.toml").into(),
+                line: 1,
+            },
+            script: Some(script.to_owned()),
+            cwd: get_current_dir().clone(),
+            depends: depends.iter().map(|name| key(name)).collect(),
+            deprecated: None,
+            confirm: None,
+            required_envs: Vec::new(),
+            service: None,
+            outputs: Vec::new(),
+            priority: 0,
+            sandbox: None,
+            allow_failure: false,
+            success_codes: vec![0],
+        }
+    }
+
+    fn rusk(tasks: impl IntoIterator<Item = (&'static str, Task)>) -> Rusk {
+        Rusk {
+            tasks: tasks
+                .into_iter()
+                .map(|(name, task)| (key(name), task))
+                .collect(),
+        }
+    }
+
+    fn opts(runner: &Rc<MockRunner>) -> ExecuteOpts {
+        ExecuteOpts {
+            runner: runner.clone(),
+            trust: Rc::new(TrustAll),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn exec_runs_dependencies_first() {
+        let runner = Rc::new(MockRunner::default());
+        let rusk = rusk([
+            ("build", task("echo build", &["generate"])),
+            ("generate", task("echo generate", &[])),
+            ("unrelated", task("echo unrelated", &[])),
+        ]);
+        rusk.exec(["build".to_owned()], opts(&runner))
+            .await
+            .unwrap();
+        assert_eq!(*runner.scripts.borrow(), ["echo generate", "echo build"]);
+    }
+
+    #[tokio::test]
+    async fn exec_stops_at_failure() {
+        let runner = Rc::new(MockRunner {
+            failing: vec!["echo generate"],
+            ..Default::default()
+        });
+        let rusk = rusk([
+            ("build", task("echo build", &["generate"])),
+            ("generate", task("echo generate", &[])),
+        ]);
+        let res = rusk.exec(["build".to_owned()], opts(&runner)).await;
+        assert!(matches!(
+            res,
+            Err(RuskError::TaskFailed(TaskError::Execution {
+                exit_code: 1,
+                ..
+            }))
+        ));
+        assert_eq!(*runner.scripts.borrow(), ["echo generate"]);
+    }
+
+    #[tokio::test]
+    async fn exec_allows_failure() {
+        let runner = Rc::new(MockRunner {
+            failing: vec!["echo generate"],
+            ..Default::default()
+        });
+        let mut generate = task("echo generate", &[]);
+        generate.allow_failure = true;
+        let rusk = rusk([
+            ("build", task("echo build", &["generate"])),
+            ("generate", generate),
+        ]);
+        let opts = opts(&runner);
+        let summary = opts.summary.clone();
+        rusk.exec(["build".to_owned()], opts).await.unwrap();
+        assert_eq!(*runner.scripts.borrow(), ["echo generate", "echo build"]);
+        assert!(summary.has_warnings());
+    }
+
+    #[tokio::test]
+    async fn env_commands_see_static_envs() {
+        let runner = Rc::new(MockRunner::default());
+        let mut build = task("echo $B", &[]);
+        build.envs = [
+            ("A".into(), EnvValue::Static("x".into())),
+            ("B".into(), EnvValue::Command("echo $A".to_owned())),
+        ]
+        .into_iter()
+        .collect();
+        let rusk = rusk([("build", build)]);
+        rusk.exec(["build".to_owned()], opts(&runner))
+            .await
+            .unwrap();
+        assert_eq!(*runner.scripts.borrow(), ["echo $A", "echo $B"]);
+        assert_eq!(runner.envs.borrow()[0][OsStr::new("A")], "x");
+    }
+
+    #[tokio::test]
+    async fn exec_records_history() {
+        let runner = Rc::new(MockRunner::default());
+        let history = Rc::new(MemoryHistory::default());
+        let rusk = rusk([("build", task("echo build", &[]))]);
+        let opts = ExecuteOpts {
+            record_history: true,
+            history: history.clone(),
+            ..opts(&runner)
+        };
+        rusk.exec(["build".to_owned()], opts).await.unwrap();
+        let stats = HistoryStore::new(get_current_dir(), &key("build"), history)
+            .stats()
+            .await
+            .unwrap();
+        assert_eq!(stats.runs, 1);
+        assert_eq!(stats.failure_rate, Some(0.0));
+    }
+
+    /// Filesystem with the files below the current directory, modified at the seconds
+    fn memory_fs(files: &[(&str, u64)]) -> Rc<MemoryFs> {
+        let fs = MemoryFs::default();
+        for (path, secs) in files {
+            fs.touch(
+                get_current_dir().join(path),
+                SystemTime::UNIX_EPOCH + Duration::from_secs(*secs),
+            );
+        }
+        Rc::new(fs)
+    }
+
+    /// Execute the File task `out.txt` depending on the files. Returns the executed scripts.
+    async fn exec_out(fs: Rc<MemoryFs>, depends: &[&str]) -> Result<Vec<String>, RuskError> {
+        let runner = Rc::new(MockRunner::default());
+        let rusk = rusk([("out.txt", task("echo out", depends))]);
+        let opts = ExecuteOpts {
+            fs,
+            ..opts(&runner)
+        };
+        rusk.exec(["out.txt".to_owned()], opts).await?;
+        Ok(runner.scripts.take())
+    }
+
+    #[tokio::test]
+    async fn up_to_date_file_task_is_skipped() {
+        let fs = memory_fs(&[("out.txt", 20), ("src.txt", 10)]);
+        assert!(exec_out(fs, &["src.txt"]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn file_task_with_stale_dependency_runs() {
+        let fs = memory_fs(&[("out.txt", 10), ("src.txt", 20)]);
+        assert_eq!(exec_out(fs, &["src.txt"]).await.unwrap(), ["echo out"]);
+    }
+
+    #[tokio::test]
+    async fn missing_file_task_runs() {
+        let fs = memory_fs(&[("src.txt", 10)]);
+        assert_eq!(exec_out(fs, &["src.txt"]).await.unwrap(), ["echo out"]);
+    }
+
+    #[tokio::test]
+    async fn missing_dependency_file_fails() {
+        let fs = memory_fs(&[("out.txt", 10), ("src.txt", 20)]);
+        fs.remove(get_current_dir().join("src.txt"));
+        let res = exec_out(fs, &["src.txt"]).await;
+        assert!(matches!(
+            res,
+            Err(RuskError::TaskFailed(
+                TaskError::DependencyFileNotFound { .. }
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn directory_dependency_uses_newest_entry() {
+        let fs = memory_fs(&[("out.txt", 20), ("src/a.rs", 10), ("src/b.rs", 15)]);
+        assert!(exec_out(fs, &["src/"]).await.unwrap().is_empty());
+        let fs = memory_fs(&[("out.txt", 20), ("src/a.rs", 10), ("src/b.rs", 30)]);
+        assert_eq!(exec_out(fs, &["src/"]).await.unwrap(), ["echo out"]);
+    }
+
+    #[tokio::test]
+    async fn missing_cwd_fails() {
+        let runner = Rc::new(MockRunner::default());
+        let rusk = rusk([("build", task("echo build", &[]))]);
+        let opts = ExecuteOpts {
+            fs: memory_fs(&[]),
+            ..opts(&runner)
+        };
+        let res = rusk.exec(["build".to_owned()], opts).await;
+        assert!(matches!(
+            res,
+            Err(RuskError::TaskUnparsable(
+                TaskParseError::DirectoryNotFound(_)
+            ))
+        ));
+    }
+
+    async fn clean_outputs(source: &Path, outputs: &[&Path]) -> Result<(), RuskError> {
+        let mut clean = task("echo clean", &[]);
+        clean.source.path = source.into();
+        clean.outputs = outputs.iter().map(|&path| path.into()).collect();
+        let removed = rusk([("clean", clean)])
+            .clean(["clean".to_owned()], true, &ExecuteOpts::default())
+            .await?;
+        assert!(removed.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clean_refuses_outputs_outside() {
+        let cwd: &Path = get_current_dir();
+        let ruskfile = cwd.join("rusk.toml");
+        let parent = cwd.parent().unwrap();
+        let inside = cwd.join("target/rusk-clean-test");
+        clean_outputs(&ruskfile, &[&inside]).await.unwrap();
+        for outputs in [&[cwd], &[parent], &[&parent.join("sibling")]] {
+            let res = clean_outputs(&ruskfile, outputs).await;
+            assert!(matches!(res, Err(RuskError::CleanOutside { .. })));
+        }
+        // The current directory is below the ruskfile of the parent, but still protected
+        let res = clean_outputs(&parent.join("rusk.toml"), &[cwd]).await;
+        assert!(matches!(res, Err(RuskError::CleanOutside { .. })));
+    }
+
+    /// Targeted service exiting with 1 by itself
+    async fn exit_service(success_codes: Vec<i32>, allow_failure: bool) -> (TaskResult, bool) {
+        let runner = Rc::new(MockRunner {
+            failing: vec!["serve"],
+            ..Default::default()
+        });
+        let mut serve = task("serve", &[]);
+        serve.service = Some(ServiceConfig {
+            ready: None,
+            ready_check: None,
+            success_codes,
+            allow_failure,
+        });
+        let opts = opts(&runner);
+        let summary = opts.summary.clone();
+        let res = match rusk([("serve", serve)])
+            .exec(["serve".to_owned()], opts)
+            .await
+        {
+            Err(RuskError::TaskFailed(err)) => Err(err),
+            res => res.map_err(|err| panic!("unexpected error: {err}")),
+        };
+        (res, summary.has_warnings())
+    }
+
+    #[tokio::test]
+    async fn service_exit_is_judged_by_success_codes() {
+        let (res, warned) = exit_service(vec![0], false).await;
+        assert!(matches!(
+            res,
+            Err(TaskError::Execution { exit_code: 1, .. })
+        ));
+        assert!(!warned);
+
+        let (res, warned) = exit_service(vec![0, 1], false).await;
+        assert!(res.is_ok() && !warned);
+    }
+
+    #[tokio::test]
+    async fn service_exit_allows_failure() {
+        let (res, warned) = exit_service(vec![0], true).await;
+        assert!(res.is_ok() && warned);
+    }
+
+    #[tokio::test]
+    async fn bench_keeps_services_running() {
+        let runner = Rc::new(MockRunner {
+            long_running: vec!["serve"],
+            ..Default::default()
+        });
+        let mut serve = task("serve", &[]);
+        serve.service = Some(ServiceConfig {
+            ready: None,
+            ready_check: None,
+            success_codes: vec![0],
+            allow_failure: false,
+        });
+        let rusk = rusk([
+            ("serve", serve),
+            ("generate", task("generate", &[])),
+            ("request", task("request", &["serve", "generate"])),
+        ]);
+        let stats = rusk
+            .bench("request".to_owned(), 3, opts(&runner))
+            .await
+            .unwrap();
+        let scripts = runner.scripts.take();
+        assert_eq!(scripts.len(), 6);
+        assert_eq!(
+            scripts[2..],
+            ["request", "request", "request", "stop serve"]
+        );
+        assert!(stats.min().is_some());
+    }
+
+    #[test]
+    fn compose_overrides_vars() {
+        let mut composer = RuskfileComposer::new();
+        composer.insert_source(
+            "/project/rusk.toml",
+            r#"
+            vars = { mode = "debug" }
+            tasks.build = { script = "cargo build --profile {{mode}}" }
+            "#,
+        );
+        let opts = ExecuteOpts {
+            vars: HashMap::from([("mode".to_owned(), "release".to_owned())]),
+            ..Default::default()
+        };
+        let rusk = Rusk::compose(composer, &opts).unwrap();
+        let script = rusk.tasks[&key("build")].script.as_deref();
+        assert_eq!(script, Some("cargo build --profile release"));
+    }
+
+    #[tokio::test]
+    async fn bench_evaluates_env_commands_once() {
+        let runner = Rc::new(MockRunner::default());
+        let mut build = task("build", &[]);
+        build.envs = HashMap::from([(
+            OsString::from("REV"),
+            EnvValue::Command("git rev-parse HEAD".to_owned()),
+        )]);
+        let stats = rusk([("build", build)])
+            .bench("build".to_owned(), 3, opts(&runner))
+            .await
+            .unwrap();
+        assert_eq!(
+            runner.scripts.take(),
+            ["git rev-parse HEAD", "build", "build", "build"]
+        );
+        assert_eq!(runner.envs.borrow().len(), 4);
+        assert!(stats.min().is_some());
+    }
+
+    #[tokio::test]
+    async fn bench_rejects_services() {
+        let runner = Rc::new(MockRunner::default());
+        let mut serve = task("serve", &[]);
+        serve.service = Some(ServiceConfig {
+            ready: None,
+            ready_check: None,
+            success_codes: vec![0],
+            allow_failure: false,
+        });
+        let res = rusk([("serve", serve)])
+            .bench("serve".to_owned(), 3, opts(&runner))
+            .await;
+        assert!(matches!(res, Err(RuskError::BenchService(_))));
+        assert!(runner.scripts.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sandboxed_task_rejects_env_commands() {
+        let runner = Rc::new(MockRunner::default());
+        let mut build = task("echo $X", &[]);
+        build.sandbox = Some(SandboxConfig {
+            network: false,
+            read_only: Vec::new(),
+            write: Vec::new(),
+        });
+        build.file_envs = HashMap::from([(
+            OsString::from("X"),
+            EnvValue::Command("echo escaped > escaped.txt".to_owned()),
+        )]);
+        let res = rusk([("build", build)])
+            .exec(["build".to_owned()], opts(&runner))
+            .await;
+        assert!(matches!(
+            res,
+            Err(RuskError::TaskUnparsable(
+                TaskParseError::SandboxedEnvCommand { .. }
+            ))
+        ));
+        assert!(runner.scripts.borrow().is_empty());
+    }
+}
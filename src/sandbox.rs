@@ -0,0 +1,501 @@
+//! Restricted execution of the scripts.
+//!
+//! A sandboxed script runs in a child process of rusk itself, which is restricted by the OS
+//! before it starts. The whole shell including the builtin commands is therefore confined.
+
+use std::{ffi::OsString, io, path::Path};
+
+use deno_task_shell::{ShellPipeReader, ShellPipeWriter};
+use tokio::process::Command;
+
+use crate::{
+    path::NormarizedPath,
+    runner::{RunRequest, ShellRunner, TaskRunner},
+    rusk::parse_script,
+};
+
+/// Environment variable passing the script to the sandboxed child process
+pub const SANDBOX_SCRIPT_ENV: &str = "RUSK_SANDBOX_SCRIPT";
+
+/// Always writable path in the sandbox
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const DEV_NULL: &str = "/dev/null";
+
+/// Restrictions applied to the script.
+/// - Only the paths listed are accessible; others are denied, except writing to `/dev/null`.
+/// - On Linux, disabling the network denies creating IPv4/IPv6 sockets.
+#[derive(Debug, Clone)]
+pub struct SandboxConfig {
+    /// Whether to allow the network access
+    pub network: bool,
+    /// Paths allowed to read and execute
+    pub read_only: Vec<NormarizedPath>,
+    /// Paths allowed to read and write
+    pub write: Vec<WritePath>,
+}
+
+/// Path allowed to read and write in the sandbox
+#[derive(Debug, Clone)]
+pub struct WritePath {
+    pub path: NormarizedPath,
+    /// Whether the path is a directory, i.e. written with the trailing `/`
+    pub dir: bool,
+}
+
+impl SandboxConfig {
+    /// Execute the script under the restrictions and return the exit code
+    pub async fn execute(&self, request: RunRequest) -> io::Result<i32> {
+        self.create_write_paths().await?;
+        let exe = std::env::current_exe()?;
+        let mut command = self.command(&exe)?;
+        command
+            .env_clear()
+            .envs(request.envs)
+            .env(SANDBOX_SCRIPT_ENV, request.script)
+            .current_dir(request.cwd)
+            .stdin(request.stdin.into_stdio())
+            .stdout(request.stdout.into_stdio())
+            .stderr(request.stderr.into_stdio())
+            .kill_on_drop(true);
+        let status = command.status().await?;
+        Ok(status.code().unwrap_or(1))
+    }
+
+    /// Create the missing write paths, which must exist to be allowed.
+    /// - Directories are created with their parents, and files as empty ones.
+    async fn create_write_paths(&self) -> io::Result<()> {
+        for WritePath { path, dir } in &self.write {
+            if *dir {
+                tokio::fs::create_dir_all(path).await?;
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            // NOTE: Not truncated if it exists
+            tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Command to start rusk itself under the restrictions
+    #[cfg(target_os = "linux")]
+    fn command(&self, exe: &Path) -> io::Result<Command> {
+        let ruleset = linux::Ruleset::new(self, exe)?;
+        let filter = if self.network {
+            None
+        } else {
+            Some(linux::SocketFilter::new()?)
+        };
+        let mut command = Command::new(exe);
+        // SAFETY: Only async-signal-safe syscalls are called after fork.
+        unsafe {
+            command.pre_exec(move || {
+                linux::no_new_privs()?;
+                if let Some(filter) = &filter {
+                    filter.install()?;
+                }
+                ruleset.restrict_self()
+            });
+        }
+        Ok(command)
+    }
+
+    /// Command to start rusk itself under the restrictions
+    #[cfg(target_os = "macos")]
+    fn command(&self, exe: &Path) -> io::Result<Command> {
+        let mut command = Command::new("/usr/bin/sandbox-exec");
+        command.arg("-p").arg(self.profile(exe)).arg(exe);
+        Ok(command)
+    }
+
+    /// Command to start rusk itself under the restrictions
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    fn command(&self, _exe: &Path) -> io::Result<Command> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sandbox is not supported on this platform",
+        ))
+    }
+
+    /// Profile of sandbox-exec
+    #[cfg(target_os = "macos")]
+    fn profile(&self, exe: &Path) -> String {
+        use std::fmt::Write;
+
+        fn quote(path: &Path) -> String {
+            let path = path.to_string_lossy();
+            format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+
+        let mut profile =
+            String::from("(version 1)\n(allow default)\n(deny file-read* file-write*)\n");
+        writeln!(profile, "(allow file-read* (literal {}))", quote(exe)).unwrap();
+        writeln!(profile, "(allow file-write* (literal \"{DEV_NULL}\"))").unwrap();
+        let write = self.write.iter().map(|write| &write.path);
+        for path in self.read_only.iter().chain(write.clone()) {
+            writeln!(profile, "(allow file-read* (subpath {}))", quote(path)).unwrap();
+        }
+        for path in write {
+            writeln!(profile, "(allow file-write* (subpath {}))", quote(path)).unwrap();
+        }
+        if !self.network {
+            profile.push_str("(deny network*)\n");
+        }
+        profile
+    }
+}
+
+/// Run the script passed from the parent rusk. Called in the sandboxed child process.
+pub async fn run_sandboxed(script: OsString) -> i32 {
+    let mut stderr = ShellPipeWriter::stderr();
+    let Some(script) = script.to_str() else {
+        let _ = stderr.write_line("rusk: sandboxed script is not valid UTF-8");
+        return 1;
+    };
+    let list = match parse_script(Some(script)) {
+        Ok(list) => list,
+        Err(err) => {
+            let _ = stderr.write_line(&format!("rusk: {err}"));
+            return 1;
+        }
+    };
+    let envs = std::env::vars_os()
+        .filter(|(name, _)| name != SANDBOX_SCRIPT_ENV)
+        .collect();
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(err) => {
+            let _ = stderr.write_line(&format!("rusk: {err}"));
+            return 1;
+        }
+    };
+    ShellRunner
+        .run(RunRequest {
+            script: script.to_owned(),
+            parsed: list,
+            envs,
+            cwd,
+            stdin: ShellPipeReader::stdin(),
+            stdout: ShellPipeWriter::stdout(),
+            stderr,
+            kill_signal: Default::default(),
+        })
+        .await
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    //! Landlock for the filesystem and seccomp for the network.
+
+    use std::{
+        ffi::CString,
+        io,
+        os::{
+            fd::{AsRawFd, FromRawFd, OwnedFd},
+            unix::ffi::OsStrExt,
+        },
+        path::Path,
+    };
+
+    use super::{DEV_NULL, SandboxConfig};
+
+    const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1 << 0;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+    /// Rights available since the ABI v1
+    const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+    /// Rights applicable to a regular file
+    const ACCESS_FILE: u64 =
+        ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+    const ACCESS_READ: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// Landlock ruleset denying the filesystem access except the allowed paths
+    pub struct Ruleset {
+        fd: OwnedFd,
+    }
+
+    impl Ruleset {
+        /// Create the ruleset from the config. `exe` is allowed to read to start.
+        pub fn new(config: &SandboxConfig, exe: &Path) -> io::Result<Self> {
+            // SAFETY: Querying the ABI version takes no pointer.
+            let abi = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_create_ruleset,
+                    std::ptr::null::<RulesetAttr>(),
+                    0,
+                    LANDLOCK_CREATE_RULESET_VERSION,
+                )
+            };
+            if abi < 1 {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("Landlock is unavailable: {}", io::Error::last_os_error()),
+                ));
+            }
+            let mut handled = ACCESS_FS_V1;
+            if abi >= 2 {
+                handled |= ACCESS_FS_REFER;
+            }
+            if abi >= 3 {
+                handled |= ACCESS_FS_TRUNCATE;
+            }
+
+            let attr = RulesetAttr {
+                handled_access_fs: handled,
+            };
+            // SAFETY: attr outlives the call and its size is passed.
+            let fd = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_create_ruleset,
+                    &attr,
+                    size_of::<RulesetAttr>(),
+                    0,
+                )
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: The syscall returned a new file descriptor owned by nobody else.
+            let ruleset = Self {
+                fd: unsafe { OwnedFd::from_raw_fd(fd as i32) },
+            };
+
+            ruleset.allow(exe, ACCESS_READ & handled)?;
+            ruleset.allow(Path::new(DEV_NULL), ACCESS_FILE & handled)?;
+            for path in &config.read_only {
+                ruleset.allow(path, ACCESS_READ & handled)?;
+            }
+            for write in &config.write {
+                ruleset.allow(&write.path, handled)?;
+            }
+            Ok(ruleset)
+        }
+
+        /// Allow the access beneath the path. Missing paths are ignored.
+        fn allow(&self, path: &Path, mut access: u64) -> io::Result<()> {
+            let Ok(metadata) = std::fs::metadata(path) else {
+                return Ok(());
+            };
+            if !metadata.is_dir() {
+                access &= ACCESS_FILE;
+            }
+            let path = CString::new(path.as_os_str().as_bytes())?;
+            // SAFETY: path is a valid C string.
+            let fd = unsafe { libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: open returned a new file descriptor owned by nobody else.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let attr = PathBeneathAttr {
+                allowed_access: access,
+                parent_fd: fd.as_raw_fd(),
+            };
+            // SAFETY: attr outlives the call.
+            let res = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    self.fd.as_raw_fd(),
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &attr,
+                    0,
+                )
+            };
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        /// Enforce the ruleset on the current process. Async-signal-safe.
+        pub fn restrict_self(&self) -> io::Result<()> {
+            // SAFETY: The file descriptor is a valid ruleset.
+            let res =
+                unsafe { libc::syscall(libc::SYS_landlock_restrict_self, self.fd.as_raw_fd(), 0) };
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+    /// Bit set in the syscall numbers of the x32 ABI, which shares [AUDIT_ARCH] with x86_64
+    #[cfg(target_arch = "x86_64")]
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// Seccomp filter denying IPv4/IPv6 sockets.
+    /// - io_uring and the x32 ABI are denied as well, since they bypass the check of `socket`.
+    pub struct SocketFilter {
+        filter: Vec<libc::sock_filter>,
+    }
+
+    impl SocketFilter {
+        /// Build the filter
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        pub fn new() -> io::Result<Self> {
+            const fn stmt(code: u32, k: u32) -> libc::sock_filter {
+                libc::sock_filter {
+                    code: code as u16,
+                    jt: 0,
+                    jf: 0,
+                    k,
+                }
+            }
+            const fn jump_if(op: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+                libc::sock_filter {
+                    code: (libc::BPF_JMP | op | libc::BPF_K) as u16,
+                    jt,
+                    jf,
+                    k,
+                }
+            }
+            const fn jump(k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+                jump_if(libc::BPF_JEQ, k, jt, jf)
+            }
+            const LOAD: u32 = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+            const RET: u32 = libc::BPF_RET | libc::BPF_K;
+            // Offsets in struct seccomp_data; the lower half of args[0] on little endian
+            const NR: u32 = 0;
+            const ARCH: u32 = 4;
+            const ARG0: u32 = 16;
+
+            // Checks of the syscall number jumping to the denial
+            let mut denied = Vec::new();
+            #[cfg(target_arch = "x86_64")]
+            denied.push((libc::BPF_JGE, X32_SYSCALL_BIT));
+            for nr in [
+                libc::SYS_io_uring_setup,
+                libc::SYS_io_uring_enter,
+                libc::SYS_io_uring_register,
+            ] {
+                denied.push((libc::BPF_JEQ, nr as u32));
+            }
+            let len = denied.len();
+
+            let mut filter = vec![
+                stmt(LOAD, ARCH),
+                jump(AUDIT_ARCH, 1, 0),
+                stmt(RET, libc::SECCOMP_RET_KILL_PROCESS),
+                stmt(LOAD, NR),
+            ];
+            // NOTE: Each check skips the rest of them and the 4 instructions of the socket check
+            filter.extend(
+                denied
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (op, k))| jump_if(op, k, (len - i + 3) as u8, 0)),
+            );
+            filter.extend([
+                jump(libc::SYS_socket as u32, 0, 4),
+                stmt(LOAD, ARG0),
+                jump(libc::AF_INET as u32, 1, 0),
+                jump(libc::AF_INET6 as u32, 0, 1),
+                stmt(RET, libc::SECCOMP_RET_ERRNO | libc::EACCES as u32),
+                stmt(RET, libc::SECCOMP_RET_ALLOW),
+            ]);
+            Ok(Self { filter })
+        }
+
+        /// Build the filter
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        pub fn new() -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "network restriction is not supported on this architecture",
+            ))
+        }
+
+        /// Install the filter on the current process. Async-signal-safe.
+        pub fn install(&self) -> io::Result<()> {
+            let prog = libc::sock_fprog {
+                len: self.filter.len() as libc::c_ushort,
+                filter: self.filter.as_ptr() as *mut _,
+            };
+            // SAFETY: prog points to the filter which outlives the call.
+            let res = unsafe {
+                libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &prog as *const libc::sock_fprog,
+                )
+            };
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    /// Forbid gaining privileges, which is required by both Landlock and seccomp
+    pub fn no_new_privs() -> io::Result<()> {
+        // SAFETY: prctl with integer arguments only.
+        let res = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn create_missing_write_paths() {
+        let root = std::env::temp_dir().join(format!("rusk-sandbox-{}", std::process::id()));
+        let existing = root.join("existing.txt");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(&existing, "kept").unwrap();
+        let write = |path: &str, dir| WritePath {
+            path: root.join(path).into(),
+            dir,
+        };
+        let config = SandboxConfig {
+            network: false,
+            read_only: Vec::new(),
+            write: vec![
+                write("single.txt", false),
+                write("nested/out.log", false),
+                write("cache", true),
+                write("existing.txt", false),
+            ],
+        };
+        let res = config.create_write_paths().await;
+        let created = (
+            root.join("single.txt").is_file(),
+            root.join("nested/out.log").is_file(),
+            root.join("cache").is_dir(),
+            std::fs::read_to_string(&existing).ok(),
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+        res.unwrap();
+        assert_eq!(created, (true, true, true, Some("kept".to_string())));
+    }
+}
@@ -0,0 +1,174 @@
+//! Job slots limiting the number of concurrently running scripts.
+
+use std::{cell::RefCell, cmp::Ordering, collections::BinaryHeap, rc::Rc};
+
+use tokio::sync::oneshot;
+
+/// Scheduler which hands out job slots to the waiting task with the highest priority.
+/// - Without a limit, every task gets a slot at once.
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    inner: Rc<RefCell<SchedulerInner>>,
+}
+
+#[derive(Default)]
+struct SchedulerInner {
+    /// Maximum number of running jobs
+    max_jobs: Option<usize>,
+    /// Number of running jobs
+    running: usize,
+    /// Tasks waiting for a slot
+    waiting: BinaryHeap<Waiter>,
+    /// Sequence number to keep FIFO order among the same priority
+    seq: u64,
+}
+
+/// Task waiting for a slot
+struct Waiter {
+    priority: i64,
+    seq: u64,
+    tx: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Higher priority first, then earlier one first
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl Scheduler {
+    /// Create a new Scheduler. `None` means unlimited.
+    pub fn new(max_jobs: Option<usize>) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(SchedulerInner {
+                max_jobs,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Wait for a job slot. The slot is released when the returned JobSlot is dropped.
+    pub async fn acquire(&self, priority: i64) -> JobSlot {
+        let rx = {
+            let mut inner = self.inner.borrow_mut();
+            if inner.max_jobs.is_none_or(|max| inner.running < max) {
+                inner.running += 1;
+                return JobSlot(self.clone());
+            }
+            let (tx, rx) = oneshot::channel();
+            let seq = inner.seq;
+            inner.seq += 1;
+            inner.waiting.push(Waiter { priority, seq, tx });
+            rx
+        };
+        // NOTE: The slot is handed over by the releaser, so `running` is not changed here.
+        rx.await.unwrap();
+        JobSlot(self.clone())
+    }
+
+    /// Hand the slot over to the next waiter, or release it
+    fn release(&self) {
+        let mut inner = self.inner.borrow_mut();
+        while let Some(Waiter { tx, .. }) = inner.waiting.pop() {
+            if tx.send(()).is_ok() {
+                return;
+            }
+            // The waiter was cancelled; try the next one
+        }
+        inner.running -= 1;
+    }
+}
+
+/// Running job slot
+pub struct JobSlot(Scheduler);
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{pin::pin, task::Poll};
+
+    use futures::{FutureExt, poll};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn unlimited_slots() {
+        let scheduler = Scheduler::new(None);
+        let slots: Vec<_> = (0..16)
+            .map(|_| scheduler.acquire(0).now_or_never().unwrap())
+            .collect();
+        assert_eq!(scheduler.inner.borrow().running, slots.len());
+    }
+
+    #[tokio::test]
+    async fn released_slot_is_reused() {
+        let scheduler = Scheduler::new(Some(1));
+        drop(scheduler.acquire(0).await);
+        assert!(scheduler.acquire(0).now_or_never().is_some());
+        assert_eq!(scheduler.inner.borrow().running, 0);
+    }
+
+    #[tokio::test]
+    async fn higher_priority_first_then_fifo() {
+        let scheduler = Scheduler::new(Some(1));
+        let running = scheduler.acquire(0).await;
+        let mut low = pin!(scheduler.acquire(0));
+        let mut high = pin!(scheduler.acquire(10));
+        let mut high_later = pin!(scheduler.acquire(10));
+        assert!(poll!(&mut low).is_pending());
+        assert!(poll!(&mut high).is_pending());
+        assert!(poll!(&mut high_later).is_pending());
+
+        drop(running);
+        let Poll::Ready(running) = poll!(&mut high) else {
+            panic!("the task with the highest priority should run first");
+        };
+        assert!(poll!(&mut high_later).is_pending());
+        assert!(poll!(&mut low).is_pending());
+
+        drop(running);
+        let Poll::Ready(running) = poll!(&mut high_later) else {
+            panic!("the earlier task should run first among the same priority");
+        };
+        assert!(poll!(&mut low).is_pending());
+
+        drop(running);
+        assert!(poll!(&mut low).is_ready());
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiter_is_skipped() {
+        let scheduler = Scheduler::new(Some(1));
+        let running = scheduler.acquire(0).await;
+        let mut cancelled = Box::pin(scheduler.acquire(10));
+        let mut waiting = pin!(scheduler.acquire(0));
+        assert!(poll!(&mut cancelled).is_pending());
+        assert!(poll!(&mut waiting).is_pending());
+        drop(cancelled);
+
+        drop(running);
+        assert!(poll!(&mut waiting).is_ready());
+    }
+}
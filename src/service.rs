@@ -0,0 +1,651 @@
+//! Long-running service tasks such as dev servers and watchers.
+
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    future::{Future, poll_fn},
+    io::{self, Write},
+    mem,
+    path::Path,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use deno_task_shell::{KillSignal, ShellPipeReader, ShellPipeWriter, SignalKind};
+use futures::{
+    FutureExt, StreamExt,
+    future::{LocalBoxFuture, join_all},
+    stream::FuturesUnordered,
+};
+use hashbrown::HashSet;
+use regex_automata::meta::Regex;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    runner::{RunRequest, TaskRunner},
+    rusk::{TaskError, TaskResult, parse_script},
+    summary::RunSummary,
+    taskkey::TaskKey,
+};
+
+/// Time to wait for services to exit after SIGTERM before sending SIGKILL
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval of polling the port
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Default time limit of the health check
+const DEFAULT_CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default interval of the health check
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Service configuration of a task
+#[derive(Clone)]
+pub struct ServiceConfig {
+    /// Condition to regard the service as started
+    pub ready: Option<ReadyCondition>,
+    /// Health check which must pass before the dependents start
+    pub ready_check: Option<ReadyCheck>,
+    /// Exit codes regarded as success when the targeted service exits
+    pub success_codes: Vec<i32>,
+    /// Whether the targeted service may exit with a failure
+    pub allow_failure: bool,
+}
+
+/// Condition to regard a service as started
+#[derive(Clone, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadyCondition {
+    /// The TCP port on localhost accepts connections
+    Port(u16),
+    /// A line of the stdout matches the regex
+    Log(LogPattern),
+    /// Fixed delay after the start
+    Delay(HumanDuration),
+}
+
+/// Regex to match the log lines
+#[derive(Clone, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct LogPattern(Regex);
+
+impl TryFrom<String> for LogPattern {
+    type Error = regex_automata::meta::BuildError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ok(LogPattern(Regex::new(&value)?))
+    }
+}
+
+/// Health check polled until the service reports healthy
+#[derive(Clone, serde::Deserialize)]
+#[serde(try_from = "ReadyCheckDeserializer")]
+pub struct ReadyCheck {
+    /// What to check
+    probe: Probe,
+    /// Time limit until the service becomes healthy
+    timeout: Duration,
+    /// Interval between the checks
+    interval: Duration,
+}
+
+/// Target of the health check
+#[derive(Clone)]
+enum Probe {
+    /// HTTP GET returns 2xx status
+    Http(HttpUrl),
+    /// TCP connection to `host:port` succeeds
+    Tcp(String),
+    /// Command exits with code 0
+    Cmd(String),
+}
+
+/// serde::Deserialize of ReadyCheck
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReadyCheckDeserializer {
+    /// URL of the HTTP health endpoint
+    url: Option<HttpUrl>,
+    /// Address of the TCP port
+    tcp: Option<String>,
+    /// Command to check the health
+    cmd: Option<String>,
+    /// Time limit until the service becomes healthy
+    timeout: Option<HumanDuration>,
+    /// Interval between the checks
+    interval: Option<HumanDuration>,
+}
+
+/// Error when parsing ReadyCheck.
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid ready_check: {0}")]
+pub struct ReadyCheckParseError(&'static str);
+
+impl TryFrom<ReadyCheckDeserializer> for ReadyCheck {
+    type Error = ReadyCheckParseError;
+
+    fn try_from(value: ReadyCheckDeserializer) -> Result<Self, Self::Error> {
+        let ReadyCheckDeserializer {
+            url,
+            tcp,
+            cmd,
+            timeout,
+            interval,
+        } = value;
+        let probe = match (url, tcp, cmd) {
+            (Some(url), None, None) => Probe::Http(url),
+            (None, Some(tcp), None) => Probe::Tcp(tcp),
+            (None, None, Some(cmd)) => Probe::Cmd(cmd),
+            _ => {
+                return Err(ReadyCheckParseError(
+                    "Exactly one of url, tcp or cmd is required",
+                ));
+            }
+        };
+        Ok(ReadyCheck {
+            probe,
+            timeout: timeout.map_or(DEFAULT_CHECK_TIMEOUT, |HumanDuration(d)| d),
+            interval: interval.map_or(DEFAULT_CHECK_INTERVAL, |HumanDuration(d)| d),
+        })
+    }
+}
+
+impl ReadyCheck {
+    /// Poll the probe until it passes. Returns false if timed out.
+    async fn wait(
+        &self,
+        envs: &std::collections::HashMap<OsString, OsString>,
+        cwd: &Path,
+        runner: &dyn TaskRunner,
+    ) -> bool {
+        tokio::time::timeout(self.timeout, async {
+            while !self.probe.check(envs, cwd, runner).await {
+                tokio::time::sleep(self.interval).await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+}
+
+impl Probe {
+    /// Check once whether the service is healthy
+    async fn check(
+        &self,
+        envs: &std::collections::HashMap<OsString, OsString>,
+        cwd: &Path,
+        runner: &dyn TaskRunner,
+    ) -> bool {
+        match self {
+            Probe::Http(url) => url.get_ok().await.unwrap_or(false),
+            Probe::Tcp(addr) => tokio::net::TcpStream::connect(addr.as_str()).await.is_ok(),
+            Probe::Cmd(cmd) => {
+                let Ok(parsed) = parse_script(Some(cmd)) else {
+                    return false;
+                };
+                let exit_code = runner
+                    .run(RunRequest {
+                        script: cmd.clone(),
+                        parsed,
+                        envs: envs.clone(),
+                        cwd: cwd.to_path_buf(),
+                        stdin: ShellPipeReader::stdin(),
+                        stdout: ShellPipeWriter::null(),
+                        stderr: ShellPipeWriter::null(),
+                        kill_signal: Default::default(),
+                    })
+                    .await;
+                exit_code == 0
+            }
+        }
+    }
+}
+
+/// URL of the plain HTTP endpoint
+#[derive(Clone, serde::Deserialize)]
+#[serde(try_from = "String")]
+struct HttpUrl {
+    /// `host:port`
+    authority: String,
+    /// Path and query beginning with '/'
+    path: String,
+}
+
+/// Error when parsing HttpUrl.
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid URL: {0}")]
+pub struct HttpUrlParseError(&'static str);
+
+impl TryFrom<String> for HttpUrl {
+    type Error = HttpUrlParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let Some(rest) = value.strip_prefix("http://") else {
+            return Err(HttpUrlParseError("Only http:// is supported"));
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() {
+            return Err(HttpUrlParseError("Host is required"));
+        }
+        // NOTE: The colons in the brackets of IPv6 addresses are not the port separator
+        let has_port = authority
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| !port.contains(']'));
+        let authority = if has_port {
+            authority.to_owned()
+        } else {
+            format!("{authority}:80")
+        };
+        Ok(HttpUrl {
+            authority,
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl HttpUrl {
+    /// Send a GET request and check whether the status is 2xx
+    async fn get_ok(&self) -> io::Result<bool> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let mut stream = tokio::net::TcpStream::connect(self.authority.as_str()).await?;
+        let host = self.authority.rsplit_once(':').map_or("", |(host, _)| host);
+        stream
+            .write_all(
+                format!(
+                    "GET {} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n\r\n",
+                    self.path
+                )
+                .as_bytes(),
+            )
+            .await?;
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line).await?;
+        // e.g. "HTTP/1.1 200 OK"
+        Ok(status_line
+            .split_whitespace()
+            .nth(1)
+            .is_some_and(|code| code.starts_with('2')))
+    }
+}
+
+/// Duration written like `500ms`, `30s`, `1.5m` or `1h`
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct HumanDuration(pub Duration);
+
+/// Error when parsing HumanDuration.
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid duration: {0}")]
+pub struct HumanDurationParseError(&'static str);
+
+impl TryFrom<String> for HumanDuration {
+    type Error = HumanDurationParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let value = value.trim();
+        let split = value
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or(HumanDurationParseError("Unit is required (ms, s, m or h)"))?;
+        let (number, unit) = value.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| HumanDurationParseError("Number is required before the unit"))?;
+        let secs = match unit {
+            "ms" => number / 1000.0,
+            "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            _ => return Err(HumanDurationParseError("Unit must be one of ms, s, m or h")),
+        };
+        Duration::try_from_secs_f64(secs)
+            .map(HumanDuration)
+            .map_err(|_| HumanDurationParseError("Duration is too long"))
+    }
+}
+
+/// Services running in one run.
+/// - Services are driven by [Services::run_until] alongside the other tasks.
+/// - Services which are not the targets are stopped by [Services::shutdown].
+#[derive(Clone)]
+pub struct Services {
+    inner: Rc<RefCell<ServicesInner>>,
+    /// Backend to run the services and the health checks
+    runner: Rc<dyn TaskRunner>,
+}
+
+#[derive(Default)]
+struct ServicesInner {
+    /// Services specified as the targets, which are kept running until they exit
+    targets: HashSet<TaskKey>,
+    /// Kill signals of the started services
+    signals: Vec<KillSignal>,
+    /// Futures of the running services
+    running: FuturesUnordered<LocalBoxFuture<'static, ()>>,
+    /// Exit codes of the targeted services, with how to judge them
+    foreground: Vec<(TaskKey, oneshot::Receiver<i32>, ServiceConfig)>,
+}
+
+impl Services {
+    /// Create a new Services with the targets of the run
+    pub fn new(targets: impl IntoIterator<Item = TaskKey>, runner: Rc<dyn TaskRunner>) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(ServicesInner {
+                targets: targets.into_iter().collect(),
+                ..Default::default()
+            })),
+            runner,
+        }
+    }
+
+    /// Start the service task and wait until it becomes ready
+    pub async fn launch(
+        &self,
+        key: TaskKey,
+        config: ServiceConfig,
+        request: RunRequest,
+    ) -> TaskResult {
+        let ServiceConfig {
+            ready, ready_check, ..
+        } = config.clone();
+        // The health check command runs in the same environment as the service
+        let check_envs = ready_check.as_ref().map(|_| request.envs.clone());
+        let cwd = request.cwd.clone();
+        let stdout = request.stdout;
+
+        // When waiting for the log, the stdout is scanned while forwarded
+        let (stdout, mut log_rx) = match &ready {
+            Some(ReadyCondition::Log(LogPattern(regex))) => {
+                let (reader, writer) = deno_task_shell::pipe();
+                let (tx, rx) = mpsc::unbounded_channel();
+                let mut scanner = LogScanner {
+                    out: stdout,
+                    regex: regex.clone(),
+                    line: Vec::new(),
+                    tx: Some(tx),
+                };
+                tokio::task::spawn_blocking(move || reader.pipe_to(&mut scanner));
+                (writer, Some(rx))
+            }
+            _ => (stdout, None),
+        };
+
+        let kill_signal = KillSignal::default();
+        let mut exited = self.start(
+            kill_signal.clone(),
+            self.runner.run(RunRequest {
+                stdout,
+                kill_signal,
+                ..request
+            }),
+        );
+
+        let ready = async {
+            match ready {
+                None => {}
+                Some(ReadyCondition::Port(port)) => {
+                    while tokio::net::TcpStream::connect(("localhost", port))
+                        .await
+                        .is_err()
+                    {
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                    }
+                }
+                Some(ReadyCondition::Log(_)) => match log_rx.as_mut().unwrap().recv().await {
+                    Some(()) => {}
+                    // The output was closed without matching: wait for the exit
+                    None => futures::future::pending().await,
+                },
+                Some(ReadyCondition::Delay(HumanDuration(delay))) => {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            match (&ready_check, &check_envs) {
+                (Some(check), Some(envs)) => check.wait(envs, &cwd, self.runner.as_ref()).await,
+                _ => true,
+            }
+        };
+        tokio::select! {
+            healthy = ready => {
+                if !healthy {
+                    return Err(TaskError::ServiceUnhealthy { key });
+                }
+            }
+            exit_code = &mut exited => {
+                return Err(TaskError::ServiceExited {
+                    key,
+                    exit_code: exit_code.unwrap_or(-1),
+                });
+            }
+        }
+
+        let mut inner = self.inner.borrow_mut();
+        if inner.targets.contains(&key) {
+            inner.foreground.push((key, exited, config));
+        }
+        Ok(())
+    }
+
+    /// Register the running service. Returns the receiver of the exit code.
+    fn start(
+        &self,
+        kill_signal: KillSignal,
+        future: impl Future<Output = i32> + 'static,
+    ) -> oneshot::Receiver<i32> {
+        let (tx, rx) = oneshot::channel();
+        let mut inner = self.inner.borrow_mut();
+        inner.signals.push(kill_signal);
+        inner.running.push(
+            async move {
+                let _ = tx.send(future.await);
+            }
+            .boxed_local(),
+        );
+        rx
+    }
+
+    /// Run the future while driving the services
+    pub async fn run_until<T>(&self, future: impl Future<Output = T>) -> T {
+        let mut future = pin!(future);
+        poll_fn(|cx| {
+            // NOTE: The future is polled first so that newly started services are polled at once.
+            if let Poll::Ready(res) = future.as_mut().poll(cx) {
+                return Poll::Ready(res);
+            }
+            let _ = self.poll_running(cx);
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Wait until all the targeted services exit.
+    /// - Exit codes are judged as the other tasks, and the allowed failures are recorded in the summary.
+    pub async fn wait_foreground(&self, summary: &RunSummary) -> TaskResult {
+        let foreground = mem::take(&mut self.inner.borrow_mut().foreground);
+        let results = join_all(
+            foreground
+                .into_iter()
+                .map(|(key, rx, config)| async move { (key, rx.await.unwrap_or(-1), config) }),
+        )
+        .await;
+        for (key, exit_code, config) in results {
+            if config.success_codes.contains(&exit_code) {
+                continue;
+            }
+            if config.allow_failure {
+                summary.record_allowed_failure(&key, exit_code);
+            } else {
+                return Err(TaskError::Execution { key, exit_code });
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop all services and wait for them to exit
+    pub async fn shutdown(&self) {
+        self.send_signal(SignalKind::SIGTERM);
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, poll_fn(|cx| self.poll_running(cx)))
+            .await
+            .is_err()
+        {
+            self.send_signal(SignalKind::SIGKILL);
+            poll_fn(|cx| self.poll_running(cx)).await;
+        }
+    }
+
+    /// Send the signal to all services
+    fn send_signal(&self, signal: SignalKind) {
+        for kill_signal in self.inner.borrow().signals.iter() {
+            kill_signal.send(signal);
+        }
+    }
+
+    /// Poll the running services. Ready when all of them exited.
+    fn poll_running(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.inner.borrow_mut();
+        loop {
+            match inner.running.poll_next_unpin(cx) {
+                Poll::Ready(Some(())) => continue,
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Writer which forwards the output and notifies when a line matches the regex
+struct LogScanner {
+    /// Destination of the output
+    out: ShellPipeWriter,
+    /// Regex to match the lines
+    regex: Regex,
+    /// Incomplete line
+    line: Vec<u8>,
+    /// Notifier. Taken when matched.
+    tx: Option<mpsc::UnboundedSender<()>>,
+}
+
+impl Write for LogScanner {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out
+            .write_all(buf)
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        if self.tx.is_some() {
+            for &b in buf {
+                if b != b'\n' {
+                    self.line.push(b);
+                    continue;
+                }
+                let line = mem::take(&mut self.line);
+                if self.regex.is_match(String::from_utf8_lossy(&line).as_ref()) {
+                    let _ = self.tx.take().unwrap().send(());
+                    break;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(value: &str) -> Result<Duration, HumanDurationParseError> {
+        HumanDuration::try_from(value.to_owned()).map(|HumanDuration(d)| d)
+    }
+
+    #[test]
+    fn human_duration_units() {
+        assert_eq!(duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(duration("1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(duration(" 1h ").unwrap(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn human_duration_errors() {
+        for value in ["", "30", "s", "1.2.3s", "5d", "-1s", "1e3s", "9e999h"] {
+            assert!(duration(value).is_err(), "{value:?}");
+        }
+        assert!(duration(&format!("{}h", "9".repeat(400))).is_err());
+    }
+
+    fn url(value: &str) -> Result<(String, String), HttpUrlParseError> {
+        HttpUrl::try_from(value.to_owned()).map(|url| (url.authority, url.path))
+    }
+
+    #[test]
+    fn http_url_defaults() {
+        let parsed = url("http://localhost").unwrap();
+        assert_eq!(parsed, ("localhost:80".to_owned(), "/".to_owned()));
+        let parsed = url("http://127.0.0.1:8080/health?full=1").unwrap();
+        assert_eq!(
+            parsed,
+            ("127.0.0.1:8080".to_owned(), "/health?full=1".to_owned())
+        );
+    }
+
+    #[test]
+    fn http_url_ipv6() {
+        assert_eq!(url("http://[::1]/").unwrap().0, "[::1]:80");
+        assert_eq!(url("http://[::1]:8080/").unwrap().0, "[::1]:8080");
+    }
+
+    #[test]
+    fn http_url_errors() {
+        for value in [
+            "https://localhost/",
+            "localhost:80",
+            "http://",
+            "http:///health",
+        ] {
+            assert!(url(value).is_err(), "{value:?}");
+        }
+    }
+
+    fn ready_check(toml: &str) -> Result<ReadyCheck, toml::de::Error> {
+        toml::from_str(toml)
+    }
+
+    #[test]
+    fn ready_check_defaults() {
+        let check = ready_check(r#"tcp = "localhost:5432""#).unwrap();
+        assert!(matches!(check.probe, Probe::Tcp(addr) if addr == "localhost:5432"));
+        assert_eq!(check.timeout, DEFAULT_CHECK_TIMEOUT);
+        assert_eq!(check.interval, DEFAULT_CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn ready_check_durations() {
+        let check = ready_check(
+            r#"
+            cmd = "pg_isready"
+            timeout = "1m"
+            interval = "100ms"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(check.probe, Probe::Cmd(_)));
+        assert_eq!(check.timeout, Duration::from_secs(60));
+        assert_eq!(check.interval, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn ready_check_requires_one_probe() {
+        assert!(ready_check("").is_err());
+        assert!(ready_check("url = \"http://localhost/\"\ntcp = \"localhost:80\"").is_err());
+        assert!(ready_check("tcp = \"localhost:80\"\nport = 80").is_err());
+        assert!(ready_check("url = \"https://localhost/\"").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! Resolved view of a task for debugging.
+
+use std::{ffi::OsString, fmt::Display};
+
+use colored::Colorize;
+use deno_task_shell::parser::SequentialList;
+
+use crate::{
+    envs::{EnvSource, EnvValue},
+    fs::TaskLocation,
+    path::NormarizedPath,
+    taskkey::TaskKey,
+};
+
+/// Fully resolved configuration of a task after composition
+pub struct TaskView {
+    /// TaskKey
+    pub key: TaskKey,
+    /// Location where the task is defined
+    pub source: TaskLocation,
+    /// Absolute working directory
+    pub cwd: NormarizedPath,
+    /// Resolved dependencies
+    pub depends: Vec<TaskKey>,
+    /// Final environment variables with their origins, sorted by name
+    pub envs: Vec<(OsString, (EnvValue, EnvSource))>,
+    /// Script as written
+    pub script: Option<String>,
+    /// Parsed script, or the parse error message
+    pub parsed: Result<SequentialList, String>,
+}
+
+impl Display for TaskView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let TaskView {
+            key,
+            source,
+            cwd,
+            depends,
+            envs,
+            script,
+            parsed,
+        } = self;
+
+        writeln!(f, "{} {}", "task".dimmed(), key)?;
+        writeln!(
+            f,
+            "{} {}:{}",
+            "source".dimmed(),
+            source.path.as_abs_str(),
+            source.line
+        )?;
+        writeln!(f, "{} {}", "cwd".dimmed(), cwd.as_abs_str())?;
+
+        write!(f, "{}", "depends".dimmed())?;
+        for dep in depends {
+            write!(f, " {}", dep)?;
+        }
+        writeln!(f)?;
+
+        writeln!(f, "{}", "envs".dimmed())?;
+        for (name, (value, source)) in envs {
+            let line = format!("{}={}", name.to_string_lossy(), value);
+            let origin = format!("({source})");
+            match source {
+                EnvSource::Global => writeln!(f, "    {} {}", line.dimmed(), origin.dimmed())?,
+                _ => writeln!(f, "    {} {}", line, origin.green())?,
+            }
+        }
+
+        writeln!(f, "{}", "script".dimmed())?;
+        for line in script.as_deref().unwrap_or_default().lines() {
+            writeln!(f, "    {}", line)?;
+        }
+
+        writeln!(f, "{}", "parsed".dimmed())?;
+        match parsed {
+            Ok(list) => {
+                for line in format!("{:#?}", list).lines() {
+                    writeln!(f, "    {}", line)?;
+                }
+            }
+            Err(err) => writeln!(f, "    {}", err.red())?,
+        }
+        Ok(())
+    }
+}
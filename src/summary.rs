@@ -0,0 +1,76 @@
+//! Summary of the execution reported after all tasks are processed.
+
+use std::{cell::RefCell, fmt::Display, rc::Rc, time::Duration};
+
+use colored::Colorize;
+
+use crate::taskkey::TaskKey;
+
+/// Outcomes of the scripts executed in a run, shared among the tasks
+#[derive(Clone, Default)]
+pub struct RunSummary {
+    inner: Rc<RefCell<RunSummaryInner>>,
+}
+
+#[derive(Default)]
+struct RunSummaryInner {
+    /// Executed scripts with their durations
+    executed: Vec<(TaskKey, Duration)>,
+    /// Tasks which failed but were allowed to, with their exit codes
+    allowed_failures: Vec<(TaskKey, i32)>,
+}
+
+impl RunSummary {
+    /// Record the script has exited, whether successfully or not
+    pub fn record_exit(&self, key: &TaskKey, duration: Duration) {
+        self.inner
+            .borrow_mut()
+            .executed
+            .push((key.clone(), duration));
+    }
+
+    /// Durations of the executed scripts of the task, in the order of the exits
+    pub fn durations(&self, key: &TaskKey) -> Vec<Duration> {
+        self.inner
+            .borrow()
+            .executed
+            .iter()
+            .filter(|(executed, _)| executed == key)
+            .map(|(_, duration)| *duration)
+            .collect()
+    }
+
+    /// Record the failure of the script is allowed
+    pub fn record_allowed_failure(&self, key: &TaskKey, exit_code: i32) {
+        self.inner
+            .borrow_mut()
+            .allowed_failures
+            .push((key.clone(), exit_code));
+    }
+
+    /// Whether there is something to warn about
+    pub fn has_warnings(&self) -> bool {
+        !self.inner.borrow().allowed_failures.is_empty()
+    }
+}
+
+impl Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.borrow();
+        write!(
+            f,
+            "{} scripts executed: {}, failures allowed: {}",
+            "Summary:".bold(),
+            inner.executed.len(),
+            inner.allowed_failures.len()
+        )?;
+        for (key, exit_code) in &inner.allowed_failures {
+            write!(
+                f,
+                "\n  {}: Task {key} failed with exit code {exit_code}",
+                "warning".yellow().bold()
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@
 use std::{
     fmt::{Debug, Display},
     hash::Hash,
-    ops::{Deref, DerefMut},
+    ops::Deref,
     path::Path,
 };
 
@@ -11,10 +11,17 @@ use colored::Colorize;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 
-use crate::path::NormarizedPath;
+use crate::{import::NAMESPACES, path::NormarizedPath};
+
+/// Prefix to mark the TaskKey as a File task explicitly
+const FILE_PREFIX: &str = "file:";
+/// Prefix to mark the TaskKey as a Phony task explicitly
+const PHONY_PREFIX: &str = "phony:";
 
 /// String representing the Phony task.
-/// Must match `^[a-zA-Z][a-zA-Z0-9_-]*$`.
+/// Must match `^[a-zA-Z][a-zA-Z0-9_-]*$`, unless written with the `phony:` prefix
+/// or namespaced as imported from another task runner, e.g. `npm:build`.
+/// - Names which don't match are kept with the prefix, so that they are distinguished from File tasks.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PhonyTaskString {
     inner: String,
@@ -56,8 +63,36 @@ impl TryFrom<String> for PhonyTaskString {
     }
 }
 
+impl PhonyTaskString {
+    /// Create from the name written after the `phony:` prefix
+    fn explicit(name: &str) -> Result<Self, PhonyTaskStringParseError> {
+        if name.is_empty() {
+            return Err(PhonyTaskStringParseError("Empty string is not allowed"));
+        }
+        if name.contains(char::is_whitespace) {
+            return Err(PhonyTaskStringParseError("Whitespace is not allowed"));
+        }
+        Ok(
+            Self::try_from(name.to_owned()).unwrap_or_else(|_| PhonyTaskString {
+                inner: format!("{PHONY_PREFIX}{name}"),
+            }),
+        )
+    }
+    /// Create from the name of a task imported from another task runner, e.g. `npm:build`
+    fn namespaced(value: String) -> Result<Self, PhonyTaskStringParseError> {
+        let (_, name) = value.split_once(':').unwrap_or_default();
+        if name.is_empty() {
+            return Err(PhonyTaskStringParseError("Empty string is not allowed"));
+        }
+        if name.contains(char::is_whitespace) {
+            return Err(PhonyTaskStringParseError("Whitespace is not allowed"));
+        }
+        Ok(PhonyTaskString { inner: value })
+    }
+}
+
 /// String representing the Path task.
-/// Must contain '/' or '.'.
+/// Must contain '/' or '.', unless written with the `file:` prefix.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PathTaskString {
     inner: String,
@@ -136,14 +171,8 @@ impl<'a> TaskKeyRef<'a> {
     pub fn as_task_key(&self) -> &TaskKey {
         self.owned.deref()
     }
-    pub fn into_task_key(mut self) -> TaskKey {
-        std::mem::replace(self.owned.deref_mut(), unsafe {
-            use std::{
-                alloc::{Layout, alloc},
-                ptr::read,
-            };
-            read(alloc(Layout::new::<TaskKey>()) as *const TaskKey)
-        })
+    pub fn into_task_key(self) -> TaskKey {
+        Lazy::into_value(self.owned).unwrap_or_else(|init| init())
     }
 }
 
@@ -199,6 +228,27 @@ impl TryFrom<String> for TaskKeyRelative {
         if value.is_empty() {
             return Err(TaskKeyParseError::Empty);
         }
+        if let Some(path) = value.strip_prefix(FILE_PREFIX) {
+            if path.is_empty() {
+                return Err(TaskKeyParseError::Empty);
+            }
+            let path = PathTaskString {
+                inner: path.to_owned(),
+            };
+            return Ok(TaskKeyRelative::File(path));
+        }
+        if let Some(name) = value.strip_prefix(PHONY_PREFIX) {
+            let phony_name = PhonyTaskString::explicit(name)?;
+            return Ok(TaskKeyRelative::Phony(phony_name));
+        }
+        if value
+            .split_once(':')
+            .is_some_and(|(namespace, _)| NAMESPACES.contains(&namespace))
+        {
+            let phony_name = PhonyTaskString::namespaced(value)?;
+            return Ok(TaskKeyRelative::Phony(phony_name));
+        }
+        // Fallback: guess from the name
         if value.contains('/') || value.contains('.') {
             let path = PathTaskString::try_from(value)?;
             return Ok(TaskKeyRelative::File(path));
@@ -238,8 +288,13 @@ impl PartialEq for TaskKey {
     }
 }
 
+/// Colored task name.
+/// - `{:#}` formats it without color, for the output read by other tools.
 impl Display for TaskKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            return f.write_str(self.as_ref());
+        }
         match self {
             TaskKey::Phony(phony_name) => write!(f, "{}", phony_name.inner.bright_purple().bold()),
             TaskKey::File(normarized_path) => {
@@ -272,3 +327,28 @@ impl From<&Self> for TaskKey {
         val.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relative(name: &str) -> TaskKeyRelative {
+        TaskKeyRelative::try_from(name.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn into_task_key_before_and_after_forcing() {
+        let base = Path::new("/project");
+        for name in ["build", "dist/app.js"] {
+            let relative = relative(name);
+            // Never forced, so the key is computed on taking it out
+            let lazy = relative.as_task_key(base).into_task_key();
+            let forced = relative.as_task_key(base);
+            let expected = forced.as_task_key().clone();
+            assert!(lazy == expected);
+            assert!(forced.into_task_key() == expected);
+        }
+        let file = relative("dist/app.js").into_task_key(base);
+        assert_eq!(file.as_ref(), "/project/dist/app.js");
+    }
+}
@@ -0,0 +1,233 @@
+//! Trust decisions of the ruskfiles outside the current repository.
+
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use futures::{FutureExt, future::LocalBoxFuture};
+use hashbrown::HashMap;
+use tokio::io::AsyncWriteExt;
+
+use crate::{lock::content_hash, path::get_current_dir, prompt::Prompter};
+
+/// File name of the trust store in the data directory of rusk
+const TRUST_FILE: &str = "trust";
+
+/// Decisions whether to trust the ruskfiles, keyed by the canonical path and the content hash.
+/// - Stored as lines of `allow|deny  hash  path`. The latest decision wins.
+pub struct TrustStore {
+    /// Path to the store
+    path: PathBuf,
+    /// Decisions
+    decisions: HashMap<(PathBuf, String), bool>,
+}
+
+impl TrustStore {
+    /// Open the store in the user data directory
+    pub async fn open() -> io::Result<Self> {
+        let path = data_dir()?.join(TRUST_FILE);
+        let content = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        let mut decisions = HashMap::new();
+        for line in content.lines() {
+            let mut fields = line.splitn(3, "  ");
+            let (Some(decision), Some(hash), Some(file)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            decisions.insert((PathBuf::from(file), hash.to_owned()), decision == "allow");
+        }
+        Ok(Self { path, decisions })
+    }
+
+    /// Decision about the ruskfile with the content hash, if recorded
+    pub fn decision(&self, file: &Path, hash: &str) -> Option<bool> {
+        self.decisions
+            .get(&(file.to_path_buf(), hash.to_owned()))
+            .copied()
+    }
+
+    /// Record the decision
+    pub async fn record(&mut self, file: &Path, hash: &str, allow: bool) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let decision = if allow { "allow" } else { "deny" };
+        let line = format!("{decision}  {hash}  {}\n", file.display());
+        let mut store = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        store.write_all(line.as_bytes()).await?;
+        self.decisions
+            .insert((file.to_path_buf(), hash.to_owned()), allow);
+        Ok(())
+    }
+}
+
+/// Data directory of rusk in the user's home
+fn data_dir() -> io::Result<PathBuf> {
+    #[cfg(windows)]
+    let base = std::env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(not(windows))]
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+    base.map(|base| base.join("rusk"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "home directory not found"))
+}
+
+/// Root of the git repository containing the current directory.
+/// - Outside a repository, the current directory itself is the root.
+async fn repository_root() -> Option<PathBuf> {
+    let cwd = tokio::fs::canonicalize(get_current_dir()).await.ok()?;
+    let mut dir = cwd.clone();
+    loop {
+        if tokio::fs::try_exists(dir.join(".git"))
+            .await
+            .unwrap_or(false)
+        {
+            return Some(dir);
+        }
+        if !dir.pop() {
+            return Some(cwd);
+        }
+    }
+}
+
+/// Ruskfiles outside the current repository with their canonical paths and content hashes.
+/// - Symlinks are resolved, so that files linked into the repository are regarded as outside.
+async fn outside_repository(
+    files: impl IntoIterator<Item = impl AsRef<Path>>,
+) -> Result<Vec<(PathBuf, String)>, TrustError> {
+    let root = repository_root().await;
+    let mut canonicals = BTreeSet::new();
+    for file in files {
+        let file = file.as_ref();
+        let canonical = tokio::fs::canonicalize(file)
+            .await
+            .map_err(|error| TrustError::Io {
+                path: file.to_path_buf(),
+                error,
+            })?;
+        if root
+            .as_ref()
+            .is_none_or(|root| !canonical.starts_with(root))
+        {
+            canonicals.insert(canonical);
+        }
+    }
+    let mut outside = Vec::new();
+    for file in canonicals {
+        let content = match tokio::fs::read(&file).await {
+            Ok(content) => content,
+            Err(error) => return Err(TrustError::Io { path: file, error }),
+        };
+        outside.push((file, content_hash(&content)));
+    }
+    Ok(outside)
+}
+
+/// Policy deciding whether the ruskfiles may be executed
+pub trait TrustPolicy {
+    /// Ensure that the ruskfiles are trusted
+    fn ensure_trusted<'a>(
+        &'a self,
+        files: Vec<&'a Path>,
+    ) -> LocalBoxFuture<'a, Result<(), TrustError>>;
+}
+
+/// Default policy asking the user about the ruskfiles outside the current repository
+pub struct PromptTrust;
+
+impl TrustPolicy for PromptTrust {
+    fn ensure_trusted<'a>(
+        &'a self,
+        files: Vec<&'a Path>,
+    ) -> LocalBoxFuture<'a, Result<(), TrustError>> {
+        confirm_trust(files, Prompter::new(false)).boxed_local()
+    }
+}
+
+/// Ensure that the ruskfiles are trusted, asking the user about the unknown ones.
+async fn confirm_trust(
+    files: impl IntoIterator<Item = impl AsRef<Path>>,
+    prompter: Prompter,
+) -> Result<(), TrustError> {
+    let outside = outside_repository(files).await?;
+    if outside.is_empty() {
+        return Ok(());
+    }
+    let mut store = TrustStore::open()
+        .await
+        .map_err(TrustError::StoreUnavailable)?;
+    for (file, hash) in outside {
+        let allow = match store.decision(&file, &hash) {
+            Some(allow) => allow,
+            None => {
+                let message = format!(
+                    "{} is outside the current repository or has changed. Trust it?",
+                    file.display()
+                );
+                let allow = prompter.confirm(&message).await.map_err(|err| {
+                    TrustError::ConfirmationUnavailable {
+                        path: file.clone(),
+                        message: err.to_string(),
+                    }
+                })?;
+                store
+                    .record(&file, &hash, allow)
+                    .await
+                    .map_err(TrustError::StoreUnavailable)?;
+                allow
+            }
+        };
+        if !allow {
+            return Err(TrustError::Denied(file));
+        }
+    }
+    Ok(())
+}
+
+/// Trust the ruskfiles outside the current repository without asking. Returns the trusted ones.
+pub async fn trust_all(
+    files: impl IntoIterator<Item = impl AsRef<Path>>,
+) -> Result<Vec<PathBuf>, TrustError> {
+    let outside = outside_repository(files).await?;
+    let mut store = TrustStore::open()
+        .await
+        .map_err(TrustError::StoreUnavailable)?;
+    let mut trusted = Vec::new();
+    for (file, hash) in outside {
+        store
+            .record(&file, &hash, true)
+            .await
+            .map_err(TrustError::StoreUnavailable)?;
+        trusted.push(file);
+    }
+    Ok(trusted)
+}
+
+/// Error of the trust check
+#[derive(Debug, thiserror::Error)]
+pub enum TrustError {
+    /// The ruskfile is not trusted
+    #[error("{} is not trusted. Run with --trust to trust it.", .0.display())]
+    Denied(PathBuf),
+    /// Failed to ask the user
+    #[error("Failed to confirm the trust of {} on the terminal: {message}", path.display())]
+    ConfirmationUnavailable { path: PathBuf, message: String },
+    /// Failed to read the ruskfile
+    #[error("Failed to read {}: {error}", path.display())]
+    Io { path: PathBuf, error: io::Error },
+    /// Failed to access the trust store
+    #[error("Failed to access the trust store: {0}")]
+    StoreUnavailable(io::Error),
+}
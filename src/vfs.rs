@@ -0,0 +1,110 @@
+//! Filesystem access of the freshness checks.
+
+#[cfg(test)]
+use std::{cell::RefCell, path::PathBuf};
+use std::{io, path::Path, time::SystemTime};
+
+#[cfg(test)]
+use futures::future::ready;
+use futures::{FutureExt, future::LocalBoxFuture};
+#[cfg(test)]
+use hashbrown::HashMap;
+
+use crate::fs::newest_modified;
+
+/// Filesystem queried to decide whether File tasks are up to date and where tasks run
+pub trait FsProvider {
+    /// Whether the path exists
+    fn exists<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, bool>;
+    /// Whether the path is an existing directory
+    fn is_dir<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, bool>;
+    /// Modified datetime of the path. For directories, the newest one among the entries below.
+    fn modified<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, io::Result<SystemTime>>;
+}
+
+/// Filesystem of the OS
+pub struct RealFs;
+
+impl FsProvider for RealFs {
+    fn exists<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, bool> {
+        async move { matches!(tokio::fs::try_exists(path).await, Ok(true)) }.boxed_local()
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, bool> {
+        async move {
+            tokio::fs::metadata(path)
+                .await
+                .is_ok_and(|metadata| metadata.is_dir())
+        }
+        .boxed_local()
+    }
+
+    fn modified<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, io::Result<SystemTime>> {
+        async move {
+            let metadata = tokio::fs::metadata(path).await?;
+            if metadata.is_dir() {
+                newest_modified(path).await
+            } else {
+                metadata.modified()
+            }
+        }
+        .boxed_local()
+    }
+}
+
+/// Filesystem on memory holding only the modified datetimes of the files, for deterministic tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryFs {
+    /// Modified datetimes of the files
+    files: RefCell<HashMap<PathBuf, SystemTime>>,
+}
+
+#[cfg(test)]
+impl MemoryFs {
+    /// Create or update the file
+    pub fn touch(&self, path: impl AsRef<Path>, modified: SystemTime) {
+        self.files
+            .borrow_mut()
+            .insert(path.as_ref().to_path_buf(), modified);
+    }
+
+    /// Remove the file
+    pub fn remove(&self, path: impl AsRef<Path>) {
+        self.files.borrow_mut().remove(path.as_ref());
+    }
+}
+
+#[cfg(test)]
+impl FsProvider for MemoryFs {
+    fn exists<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, bool> {
+        // Directories exist implicitly when they have files below
+        let exists = self
+            .files
+            .borrow()
+            .keys()
+            .any(|file| file.starts_with(path));
+        ready(exists).boxed_local()
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, bool> {
+        let is_dir = self
+            .files
+            .borrow()
+            .keys()
+            .any(|file| file != path && file.starts_with(path));
+        ready(is_dir).boxed_local()
+    }
+
+    fn modified<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, io::Result<SystemTime>> {
+        let newest = self
+            .files
+            .borrow()
+            .iter()
+            .filter(|(file, _)| file.starts_with(path))
+            .map(|(_, modified)| *modified)
+            .max()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound));
+        ready(newest).boxed_local()
+    }
+}